@@ -3,18 +3,110 @@ extern crate criterion;
 extern crate endo_rs;
 
 use criterion::Criterion;
-use endo_rs::DnaExecutor;
+use endo_rs::{Dna, DnaExecutor, DnaRope, DnaRopeIter, ParseMode, Searcher};
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
+const ENDO_DNA: &str = "data/endo.dna";
 
-fn endo_decode(loops: usize) {
-    let mut dna_executor = DnaExecutor::from(ENDO_DNA);
+fn endo_decode(dna: &DnaRope, loops: usize) {
+    let mut dna_executor = DnaExecutor::from_rope(dna.clone());
     dna_executor.execute_loops(loops);
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("endo decode 1000", |b| b.iter(|| endo_decode(1000)));
+    let dna = match DnaRope::from_path(ENDO_DNA, ParseMode::Lenient) {
+        Ok(dna) => dna,
+        Err(e) => {
+            eprintln!("skipping endo benchmarks, {}: {}", ENDO_DNA, e);
+            return;
+        }
+    };
+    c.bench_function("endo decode 1000", move |b| {
+        b.iter(|| endo_decode(&dna, 1000))
+    });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Pseudo-random DNA of `len` bases split into chunks of 1000 bases.
+fn synthetic_dna(len: usize) -> (Vec<Dna>, DnaRope) {
+    let bases = [Dna::I, Dna::C, Dna::F, Dna::P];
+    let mut state: u32 = 0x1234_5678;
+    let dna: Vec<_> = (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            bases[(state >> 7) as usize % 4]
+        })
+        .collect();
+    let mut dna_rope = DnaRope::new();
+    for chunk in dna.chunks(1000) {
+        dna_rope.append_dna(chunk.to_vec());
+    }
+    (dna, dna_rope)
+}
+
+/// `Iter::dna_search` as it was before the word-window search, a KMP automaton
+/// built on every call and fed one base at a time.
+fn baseline_dna_search(iter: &DnaRopeIter, key: &[Dna]) -> Option<usize> {
+    if key.is_empty() {
+        return None;
+    }
+
+    let key_len = key.len();
+    if key_len == 1 {
+        let key = key[0];
+        let mut iter = iter.clone();
+        let pos = iter.pos();
+        return iter.position(|&x| x == key).map(|x| pos + x);
+    }
+
+    let mut dfa = vec![[0; 4]; key_len];
+    dfa[0][key[0] as usize] = 1;
+    let mut x = 0;
+    for j in 1..key_len {
+        dfa[j] = dfa[x];
+        let k = key[j] as usize;
+        dfa[j][k] = j + 1;
+        x = dfa[x][k];
+    }
+
+    let mut iter = iter.clone();
+    let mut j = 0;
+    while let Some(dna) = iter.next() {
+        j = dfa[j][*dna as usize];
+        if j == key_len {
+            return Some(iter.pos() - key_len);
+        }
+    }
+    None
+}
+
+/// Compares the baseline search, the current `Iter::dna_search` and the
+/// cached `Searcher` used by `Pattern::Search`.
+fn search_benchmark(c: &mut Criterion) {
+    let (dna, dna_rope) = synthetic_dna(1 << 20);
+    for &len in &[8, 24, 64] {
+        let key = dna[dna.len() - 100..dna.len() - 100 + len].to_vec();
+
+        let (dna_rope_baseline, key_baseline) = (dna_rope.clone(), key.clone());
+        assert_eq!(
+            baseline_dna_search(&dna_rope.iter(), &key),
+            dna_rope.iter().dna_search(&key)
+        );
+        c.bench_function(&format!("search {} bases baseline kmp", len), move |b| {
+            b.iter(|| baseline_dna_search(&dna_rope_baseline.iter(), &key_baseline))
+        });
+
+        let (dna_rope_kmp, key_kmp) = (dna_rope.clone(), key.clone());
+        c.bench_function(&format!("search {} bases dna_search", len), move |b| {
+            b.iter(|| dna_rope_kmp.iter().dna_search(&key_kmp))
+        });
+
+        let (dna_rope_horspool, searcher) = (dna_rope.clone(), Searcher::new(&key));
+        c.bench_function(&format!("search {} bases searcher", len), move |b| {
+            b.iter(|| searcher.find(&dna_rope_horspool.iter()))
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark, search_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,19 @@
+extern crate endo_rs;
+
+use endo_rs::assemble;
+
+use std::env::args;
+use std::fs::read_to_string;
+use std::process::exit;
+
+fn main() {
+    let path = args().nth(1).expect("usage: asm <source>");
+    let source = read_to_string(&path).unwrap();
+    match assemble(&source) {
+        Ok(dna) => println!("{}", dna.iter().map(|x| x.to_string()).collect::<String>()),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            exit(1);
+        }
+    }
+}
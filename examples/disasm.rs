@@ -0,0 +1,21 @@
+extern crate endo_rs;
+
+use endo_rs::{DnaRope, Instruction, ParseMode, disassemble};
+
+use std::env::args;
+
+fn main() {
+    let path = args().nth(1).expect("usage: disasm <dna file> [rules]");
+    let rules = args().nth(2).map_or(usize::MAX, |x| x.parse().unwrap());
+    let dna = DnaRope::from_path(path, ParseMode::Lenient).unwrap();
+    let mut listed = 0;
+    for instruction in disassemble(&dna) {
+        if !matches!(instruction, Instruction::Rna { .. }) {
+            if listed == rules {
+                break;
+            }
+            listed += 1;
+        }
+        println!("{}", instruction);
+    }
+}
@@ -3,17 +3,28 @@ extern crate simplelog;
 
 extern crate endo_rs;
 
-use log::info;
+use log::{debug, info};
 use simplelog::{CombinedLogger, Config, LevelFilter, TermLogger, WriteLogger};
 
 use std::env::args;
 use std::fs::File;
+use std::io::Read;
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
-
-use endo_rs::DnaExecutor;
+use endo_rs::{DnaExecutor, ExecutionStatus, Observer, ParseMode, Progress};
 use endo_rs::{Rna, RnaRenderer};
 
+struct ProgressLogger;
+
+impl Observer for ProgressLogger {
+    fn progress(&mut self, progress: &Progress) {
+        debug!("{:?}", progress);
+    }
+
+    fn stopped(&mut self, status: ExecutionStatus, progress: &Progress) {
+        info!("execution stopped: {:?} {:?}", status, progress);
+    }
+}
+
 fn main() {
     let _ = CombinedLogger::init(vec![
         TermLogger::new(LevelFilter::Info, Config::default()).unwrap(),
@@ -27,27 +38,26 @@ fn main() {
     // IIPIFFCPICICIICPIICIPPPICIIC - self check
     let prefix = if let Some(prefix) = args().nth(1) {
         info!("Running with prefix: {}", prefix);
-        prefix + ENDO_DNA
+        prefix
     } else {
         info!("Running with empty prefix");
-        ENDO_DNA.into()
+        String::new()
     };
-    let mut dna_executor = DnaExecutor::from(&prefix[..]);
-    loop {
-        let is_finished = dna_executor.execute_loops(1000);
-
-        if is_finished {
-            break;
-        }
-    }
-    let dummy = [Rna::Unknown(vec![])];
+    let genome = args().nth(2).unwrap_or_else(|| "data/endo.dna".into());
+    info!("Loading genome: {}", genome);
+    let dna = prefix.as_bytes().chain(File::open(genome).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
+    dna_executor.observe(ProgressLogger, 1000);
+    dna_executor.execute();
+    info!("defragmentation: {:?}", dna_executor.defragment_stats());
+    let dummy = [Rna::Truncated(vec![])];
 
     let rna = dna_executor.rna();
 
     info!("rna len: {}", rna.len());
 
     let mut renderer = RnaRenderer::new();
-    let mut last_command = &Rna::Unknown(vec![]);
+    let mut last_command = &Rna::Truncated(vec![]);
     let mut same_command_count = 0;
 
     for (index, command) in rna.iter().chain(dummy.iter()).enumerate() {
@@ -55,7 +65,7 @@ fn main() {
             same_command_count += 1;
         } else {
             match last_command {
-                Rna::Unknown(_) => (),
+                Rna::Unknown(_) | Rna::Truncated(_) => (),
                 _ => println!(
                     "{} {:?}{} {}",
                     index,
@@ -85,4 +95,13 @@ fn main() {
         }
         renderer.render_command(command);
     }
+
+    // The dummy command closing the listing is reported as well.
+    for diagnostic in renderer
+        .diagnostics()
+        .iter()
+        .filter(|x| x.index < rna.len())
+    {
+        println!("{} not rendered: {}", diagnostic.index, diagnostic.command);
+    }
 }
@@ -2,17 +2,17 @@
 extern crate cpuprofiler;
 extern crate endo_rs;
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
-
 #[cfg(feature = "with_cpuprofiler")]
 use cpuprofiler::PROFILER;
-use endo_rs::DnaExecutor;
+use endo_rs::{DnaExecutor, Observer, ParseMode, Progress};
 
-fn main() {
-    let mut dna_executor = DnaExecutor::from(ENDO_DNA);
-    let mut loops_count = 0;
-    while !dna_executor.execute_loops(40000) {
-        loops_count += 40000;
+use std::env::args;
+
+struct ProgressPrinter;
+
+impl Observer for ProgressPrinter {
+    fn progress(&mut self, progress: &Progress) {
+        let loops_count = progress.iterations;
         println!("{}", loops_count);
 
         #[cfg(feature = "with_cpuprofiler")]
@@ -33,3 +33,10 @@ fn main() {
         }
     }
 }
+
+fn main() {
+    let genome = args().nth(1).unwrap_or_else(|| "data/endo.dna".into());
+    let mut dna_executor = DnaExecutor::from_path(genome, ParseMode::Lenient).unwrap();
+    dna_executor.observe(ProgressPrinter, 40000);
+    dna_executor.execute();
+}
@@ -1,16 +1,24 @@
+#[cfg(feature = "with_cpuprofiler")]
 extern crate cpuprofiler;
 extern crate endo_rs;
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
-
+#[cfg(feature = "with_cpuprofiler")]
 use cpuprofiler::PROFILER;
-use endo_rs::DnaExecutor;
+use endo_rs::{DnaExecutor, ParseMode};
+
+use std::env::args;
+use std::fs::File;
+use std::io::Read;
 
 fn main() {
-    let dna = "IIPIFFCPICICIICPIICIPPPICIIC".to_string() + ENDO_DNA;
-    let mut dna_executor = DnaExecutor::from(dna.as_str());
+    let genome = args().nth(1).unwrap_or_else(|| "data/endo.dna".into());
+    let dna = "IIPIFFCPICICIICPIICIPPPICIIC"
+        .as_bytes()
+        .chain(File::open(genome).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
 
     // Unlock the mutex and start the profiler
+    #[cfg(feature = "with_cpuprofiler")]
     PROFILER
         .lock()
         .unwrap()
@@ -19,5 +27,6 @@ fn main() {
     dna_executor.execute();
 
     // Unwrap the mutex and stop the profiler
+    #[cfg(feature = "with_cpuprofiler")]
     PROFILER.lock().unwrap().stop().expect("Couldn't stop");
 }
@@ -1,12 +1,17 @@
 extern crate endo_rs;
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
+use endo_rs::{DnaExecutor, ParseMode};
 
-use endo_rs::DnaExecutor;
+use std::env::args;
+use std::fs::File;
+use std::io::Read;
 
 fn main() {
-    let dna = "IIPIFFCPICICIICPIICIPPPICIIC".to_string() + ENDO_DNA;
+    let genome = args().nth(1).unwrap_or_else(|| "data/endo.dna".into());
+    let dna = "IIPIFFCPICICIICPIICIPPPICIIC"
+        .as_bytes()
+        .chain(File::open(genome).unwrap());
 
-    let mut dna_executor = DnaExecutor::from(dna.as_str());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
     dna_executor.execute();
 }
@@ -0,0 +1,36 @@
+extern crate log;
+extern crate simplelog;
+
+extern crate endo_rs;
+
+use log::info;
+use simplelog::{Config, LevelFilter, TermLogger};
+
+use std::env::args;
+use std::fs::File;
+use std::io::Read;
+
+use endo_rs::{DnaExecutor, ParseMode, RnaRenderer};
+
+/// Renders the picture of a prefix without a display, e.g.
+/// `cargo run --release --example render -- IIPIFFCPICICIICPIICIPPPICIIC data/endo.dna selfcheck.png`
+/// writes the top bitmap to `selfcheck.png`, the format following the
+/// extension (`png`, `ppm` or `pam`).
+fn main() {
+    let _ = TermLogger::init(LevelFilter::Info, Config::default());
+    let prefix = args().nth(1).unwrap_or_default();
+    let genome = args().nth(2).unwrap_or_else(|| "data/endo.dna".into());
+    let output = args().nth(3).unwrap_or_else(|| "endo.png".into());
+    info!("Running genome {} with prefix {:?}", genome, prefix);
+    let dna = prefix.as_bytes().chain(File::open(genome).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
+    dna_executor.execute();
+
+    let mut renderer = RnaRenderer::new();
+    renderer.render(dna_executor.rna());
+    for diagnostic in renderer.diagnostics() {
+        info!("{} not rendered: {}", diagnostic.index, diagnostic.command);
+    }
+    renderer.save_image(0, &output).unwrap();
+    info!("Wrote {}", output);
+}
@@ -0,0 +1,30 @@
+extern crate endo_rs;
+
+use std::env::args;
+use std::fs::File;
+use std::io::Read;
+
+use endo_rs::{DnaExecutor, ParseMode, RnaRenderer, TargetImage};
+
+/// Scores a prefix against the target picture, e.g.
+/// `cargo run --release --example score -- IIPIFFCPICICIICPIICIPPPICIIC data/endo.dna data/target.png mask.png`
+/// prints the number of incorrect pixels and the risk, and writes the
+/// incorrect pixels to `mask.png` when given.
+fn main() {
+    let prefix = args().nth(1).unwrap_or_default();
+    let genome = args().nth(2).unwrap_or_else(|| "data/endo.dna".into());
+    let target = args().nth(3).unwrap_or_else(|| "data/target.png".into());
+    let target = TargetImage::load(target).unwrap();
+    let dna = prefix.as_bytes().chain(File::open(genome).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
+
+    let mut renderer = RnaRenderer::with_size(target.width(), target.height());
+    renderer.render(dna_executor.execute());
+    let score = target.score(&renderer).unwrap();
+    println!("incorrect pixels: {}", score.incorrect_pixels);
+    println!("similarity: {:.4}", score.similarity());
+    println!("risk: {}", score.risk(prefix.len()));
+    if let Some(mask) = args().nth(4) {
+        score.save_mask(mask).unwrap();
+    }
+}
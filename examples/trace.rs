@@ -0,0 +1,72 @@
+extern crate endo_rs;
+
+use endo_rs::{
+    BinaryTraceWriter, DnaExecutor, JsonTraceWriter, ParseMode, TraceFilter, TraceReader,
+};
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+
+const USAGE: &str = "usage:
+    trace record <prefix> <genome> <trace> [loops]
+    trace json <prefix> <genome> <jsonl> [loops]
+    trace replay <trace> [first iteration] [last iteration]";
+
+fn execute(args: &[String], json: bool) {
+    let dna = args[0].as_bytes().chain(File::open(&args[1]).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
+    let out = BufWriter::new(File::create(&args[2]).unwrap());
+    if json {
+        dna_executor.trace_to(JsonTraceWriter::new(out));
+    } else {
+        dna_executor.trace_to(BinaryTraceWriter::new(out).unwrap());
+    }
+    let loops = args.get(3).map_or(usize::MAX, |x| x.parse().unwrap());
+    dna_executor.execute_loops(loops);
+    dna_executor.take_tracer();
+    if let Some(e) = dna_executor.trace_error() {
+        panic!("trace failed: {}", e);
+    }
+}
+
+fn replay(args: &[String]) {
+    let reader = TraceReader::new(BufReader::new(File::open(&args[0]).unwrap())).unwrap();
+    let first = args.get(1).map_or(0, |x| x.parse().unwrap());
+    let last = args.get(2).map_or(usize::MAX, |x| x.parse().unwrap());
+    let filter = TraceFilter {
+        iterations: Some(first..last.saturating_add(1)),
+        ..TraceFilter::default()
+    };
+    for event in reader.filter_events(filter) {
+        let event = event.unwrap();
+        let step = &event.step;
+        println!(
+            "{} {} -> {} prefix: {} position: {:?} env: {:?} dna len: {} rna: {:?}",
+            event.iteration,
+            step.pattern
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<String>(),
+            step.template
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<String>(),
+            step.prefix_len,
+            step.position,
+            step.env,
+            event.dna_len,
+            step.rna
+        );
+    }
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("record") if args.len() >= 4 => execute(&args[1..], false),
+        Some("json") if args.len() >= 4 => execute(&args[1..], true),
+        Some("replay") if args.len() >= 2 => replay(&args[1..]),
+        _ => eprintln!("{}", USAGE),
+    }
+}
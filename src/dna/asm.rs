@@ -0,0 +1,465 @@
+//! Assembler for DNA prefixes.
+//!
+//! The source is line oriented, `#` starts a comment:
+//!
+//! ```text
+//! gene AAA_geneTablePageNr 0x510 0x18   # gene table entry: NAME OFFSET SIZE
+//! const page 42                         # named number
+//! start:                                # label, the offset of the next base
+//! rule (?[IFPP])F -> (0)                # pattern -> template, `{expr}` is substituted
+//! rule ![{AAA_geneTablePageNr.offset}] -> |0|
+//! dna IIPIFF                            # literal DNA
+//! nat page + 1                          # number encoded as `asnat`
+//! quote 2 ICFP                          # DNA protected with the given level
+//! rna PIPIIIC                           # RNA command, emitted as `III` and 7 bases
+//! ```
+//!
+//! Numbers are decimal or `0x` hexadecimal and may be combined with `+` and `-`.
+//! A gene name alone stands for its offset, `NAME.size` for its size.
+//! Labels are made of letters, digits and `_`, not starting with a digit nor
+//! made of bases only. They may be referenced before they are defined, but
+//! not from `gene` or `const`.
+
+use super::{
+    Dna::{self, *},
+    NotationError, ParseError, ParseMode,
+    matchreplace::{asnat, protect},
+    parse_dna, pattern, template,
+};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const MAX_LAYOUT_PASSES: usize = 32;
+/// Longest DNA a `quote` may produce, the contest's limit on the DNA length.
+const MAX_QUOTED_LEN: usize = 25_000_000;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AsmErrorKind {
+    UnknownDirective(String),
+    UnknownSymbol(String),
+    DuplicateSymbol(String),
+    InvalidLabel(String),
+    InvalidNumber(String),
+    MissingOperand,
+    MissingArrow,
+    Overflow,
+    QuoteTooLong,
+    InvalidDna(ParseError),
+    InvalidRna(String),
+    InvalidNotation(NotationError),
+    LayoutDiverges,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownDirective(name) => write!(f, "unknown directive {:?}", name),
+            AsmErrorKind::UnknownSymbol(name) => write!(f, "unknown symbol {:?}", name),
+            AsmErrorKind::DuplicateSymbol(name) => write!(f, "duplicate symbol {:?}", name),
+            AsmErrorKind::InvalidLabel(text) => write!(f, "invalid label {:?}", text),
+            AsmErrorKind::InvalidNumber(text) => write!(f, "invalid number {:?}", text),
+            AsmErrorKind::MissingOperand => write!(f, "missing operand"),
+            AsmErrorKind::MissingArrow => write!(f, "rule without `->`"),
+            AsmErrorKind::Overflow => write!(f, "number overflow"),
+            AsmErrorKind::QuoteTooLong => {
+                write!(f, "quoted DNA longer than {} bases", MAX_QUOTED_LEN)
+            }
+            AsmErrorKind::InvalidDna(e) => write!(f, "invalid DNA: {}", e),
+            AsmErrorKind::InvalidRna(text) => write!(f, "RNA command must be 7 bases: {:?}", text),
+            AsmErrorKind::InvalidNotation(e) => write!(f, "invalid notation: {}", e),
+            AsmErrorKind::LayoutDiverges => write!(f, "label offsets do not settle"),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+enum Statement<'a> {
+    Label(&'a str),
+    Rule(&'a str, &'a str),
+    Dna(Vec<Dna>),
+    Nat(&'a str),
+    Quote(&'a str, Vec<Dna>),
+}
+
+struct Line<'a> {
+    number: usize,
+    statement: Statement<'a>,
+}
+
+#[derive(Default)]
+struct Symbols {
+    consts: HashMap<String, usize>,
+    genes: HashMap<String, (usize, usize)>,
+    labels: HashMap<String, usize>,
+}
+
+impl Symbols {
+    fn is_defined(&self, name: &str) -> bool {
+        self.consts.contains_key(name)
+            || self.genes.contains_key(name)
+            || self.labels.contains_key(name)
+    }
+
+    fn lookup(&self, name: &str) -> Result<usize, AsmErrorKind> {
+        let value = match name.split_once('.') {
+            Some((gene, "offset")) => self.genes.get(gene).map(|x| x.0),
+            Some((gene, "size")) => self.genes.get(gene).map(|x| x.1),
+            Some(_) => None,
+            None => self
+                .consts
+                .get(name)
+                .or_else(|| self.labels.get(name))
+                .cloned()
+                .or_else(|| self.genes.get(name).map(|x| x.0)),
+        };
+        value.ok_or_else(|| AsmErrorKind::UnknownSymbol(name.into()))
+    }
+
+    fn eval(&self, expr: &str) -> Result<usize, AsmErrorKind> {
+        let mut result: usize = 0;
+        let mut negative = false;
+        let mut rest = expr.trim();
+        if rest.is_empty() {
+            return Err(AsmErrorKind::MissingOperand);
+        }
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+            if term.is_empty() {
+                return Err(AsmErrorKind::MissingOperand);
+            }
+            let value = self.term(term)?;
+            result = if negative {
+                result.checked_sub(value)
+            } else {
+                result.checked_add(value)
+            }
+            .ok_or(AsmErrorKind::Overflow)?;
+            if end == rest.len() {
+                return Ok(result);
+            }
+            negative = rest[end..].starts_with('-');
+            rest = &rest[end + 1..];
+        }
+    }
+
+    /// Evaluates `expr` without labels, as their offsets are not known yet.
+    fn eval_constant(&mut self, expr: &str) -> Result<usize, AsmErrorKind> {
+        let labels = std::mem::take(&mut self.labels);
+        let value = self.eval(expr);
+        self.labels = labels;
+        value
+    }
+
+    fn term(&self, term: &str) -> Result<usize, AsmErrorKind> {
+        let number = if let Some(hex) = term.strip_prefix("0x") {
+            usize::from_str_radix(hex, 16)
+        } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+            term.parse()
+        } else {
+            return self.lookup(term);
+        };
+        number.map_err(|_| AsmErrorKind::InvalidNumber(term.into()))
+    }
+
+    /// Replaces every `{expr}` in `text` with its value.
+    fn substitute(&self, text: &str) -> Result<String, AsmErrorKind> {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or(AsmErrorKind::MissingOperand)?;
+            result.push_str(&rest[..start]);
+            result.push_str(&self.eval(&rest[start + 1..start + end])?.to_string());
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+fn dna_operand(text: &str) -> Result<Vec<Dna>, AsmErrorKind> {
+    parse_dna(text, ParseMode::Lenient).map_err(AsmErrorKind::InvalidDna)
+}
+
+/// Whether `label` is an identifier which cannot be mistaken for DNA.
+fn is_label(label: &str) -> bool {
+    label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !label.chars().all(|c| "ICFP".contains(c))
+}
+
+/// Length of `bases` protected `level` times, `None` if longer than
+/// [`MAX_QUOTED_LEN`]. Only the number of every base is followed, as
+/// quoting turns I, C, F and P into C, F, P and IC.
+fn quoted_len(bases: &[Dna], level: usize) -> Option<usize> {
+    let mut counts = [0; 4];
+    for &base in bases {
+        counts[base as usize] += 1;
+    }
+    let mut len = bases.len();
+    for _ in 0..level {
+        if len == 0 || len > MAX_QUOTED_LEN {
+            break;
+        }
+        let [i, c, f, p] = counts;
+        counts = [p, i + p, c, f];
+        len += p;
+    }
+    (len <= MAX_QUOTED_LEN).then_some(len)
+}
+
+fn parse_line<'a>(
+    text: &'a str,
+    symbols: &mut Symbols,
+) -> Result<Option<Statement<'a>>, AsmErrorKind> {
+    let text = text.split('#').next().unwrap_or_default().trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    if let Some(label) = text.strip_suffix(':') {
+        let label = label.trim();
+        if !is_label(label) {
+            return Err(AsmErrorKind::InvalidLabel(label.into()));
+        }
+        if symbols.is_defined(label) {
+            return Err(AsmErrorKind::DuplicateSymbol(label.into()));
+        }
+        symbols.labels.insert(label.into(), 0);
+        return Ok(Some(Statement::Label(label)));
+    }
+    let (directive, operand) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let operand = operand.trim();
+    let mut words = operand.split_whitespace();
+    let statement = match directive {
+        "gene" | "const" => {
+            let name = words.next().ok_or(AsmErrorKind::MissingOperand)?;
+            if symbols.is_defined(name) {
+                return Err(AsmErrorKind::DuplicateSymbol(name.into()));
+            }
+            if directive == "gene" {
+                let offset =
+                    symbols.eval_constant(words.next().ok_or(AsmErrorKind::MissingOperand)?)?;
+                let size =
+                    symbols.eval_constant(words.next().ok_or(AsmErrorKind::MissingOperand)?)?;
+                symbols.genes.insert(name.into(), (offset, size));
+            } else {
+                let value = symbols.eval_constant(operand[name.len()..].trim())?;
+                symbols.consts.insert(name.into(), value);
+            }
+            return Ok(None);
+        }
+        "rule" => {
+            let (pattern, template) = operand
+                .split_once("->")
+                .or_else(|| operand.split_once('→'))
+                .ok_or(AsmErrorKind::MissingArrow)?;
+            Statement::Rule(pattern, template)
+        }
+        "dna" => Statement::Dna(dna_operand(operand)?),
+        "nat" => Statement::Nat(operand),
+        "quote" => {
+            let level = words.next().ok_or(AsmErrorKind::MissingOperand)?;
+            Statement::Quote(level, dna_operand(operand[level.len()..].trim())?)
+        }
+        "rna" => {
+            let command = dna_operand(operand)?;
+            if command.len() != 7 {
+                return Err(AsmErrorKind::InvalidRna(operand.into()));
+            }
+            let mut dna = vec![I, I, I];
+            dna.extend(command);
+            Statement::Dna(dna)
+        }
+        _ => return Err(AsmErrorKind::UnknownDirective(directive.into())),
+    };
+    Ok(Some(statement))
+}
+
+fn emit(statement: &Statement, symbols: &Symbols, dna: &mut Vec<Dna>) -> Result<(), AsmErrorKind> {
+    match statement {
+        Statement::Label(_) => (),
+        Statement::Rule(p, t) => {
+            let p =
+                pattern::parse(&symbols.substitute(p)?).map_err(AsmErrorKind::InvalidNotation)?;
+            let t =
+                template::parse(&symbols.substitute(t)?).map_err(AsmErrorKind::InvalidNotation)?;
+            dna.extend(pattern::encode(&p));
+            dna.extend(template::encode(&t));
+        }
+        Statement::Dna(bases) => dna.extend_from_slice(bases),
+        Statement::Nat(expr) => dna.extend(asnat(symbols.eval(expr)?)),
+        Statement::Quote(level, bases) => {
+            let level = symbols.eval(level)?;
+            // `protect` quotes once per level, even nothing.
+            if quoted_len(bases, level).ok_or(AsmErrorKind::QuoteTooLong)? > 0 {
+                dna.extend(protect(level, bases));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Assembles `source` into a DNA prefix.
+pub fn assemble(source: &str) -> Result<Vec<Dna>, AsmError> {
+    let mut symbols = Symbols::default();
+    let mut lines = vec![];
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        if let Some(statement) =
+            parse_line(text, &mut symbols).map_err(|kind| AsmError { line: number, kind })?
+        {
+            lines.push(Line { number, statement });
+        }
+    }
+
+    // Label offsets depend on the length of numbers referring to them,
+    // so the layout is repeated until it settles.
+    for _ in 0..MAX_LAYOUT_PASSES {
+        let mut dna = vec![];
+        let mut changed = false;
+        for line in &lines {
+            if let Statement::Label(label) = line.statement {
+                let offset = dna.len();
+                if symbols.labels.insert(label.into(), offset) != Some(offset) {
+                    changed = true;
+                }
+            }
+            emit(&line.statement, &symbols, &mut dna).map_err(|kind| AsmError {
+                line: line.number,
+                kind,
+            })?;
+        }
+        if !changed {
+            return Ok(dna);
+        }
+    }
+    Err(AsmError {
+        line: lines.last().map_or(0, |x| x.number),
+        kind: AsmErrorKind::LayoutDiverges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Pattern, Template, read_dna};
+    use super::*;
+
+    #[test]
+    fn selfcheck() {
+        assert_eq!(
+            assemble("rule (?[IFPP])F -> (0)P\n"),
+            Ok(read_dna("IIPIFFCPICICIICPIICIFPPICIIC"))
+        );
+    }
+
+    #[test]
+    fn directives() {
+        let source = "
+            # activate a gene
+            gene AAA_geneTablePageNr 0x510 0x18
+            const page 2
+            rule ![{AAA_geneTablePageNr + 1}] -> |{page - 2}|
+            dna ICF P
+            nat page+1
+            quote 1 P
+            rna PIIIIIP
+        ";
+        let mut expected = pattern::encode(&[Pattern::Skip(0x511)]);
+        expected.extend(template::encode(&[Template::Length(0)]));
+        expected.extend(read_dna("ICFPCCPICIIIPIIIIIP"));
+        assert_eq!(assemble(source), Ok(expected));
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            nat end
+            dna IIIIIIIIII
+            end:
+        ";
+        // `end` settles at 15: asnat(15) is `CCCCP` followed by ten bases.
+        assert_eq!(assemble(source), Ok(read_dna("CCCCPIIIIIIIIII")));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            assemble("quote 200 P"),
+            Err(AsmError {
+                line: 1,
+                kind: AsmErrorKind::QuoteTooLong
+            })
+        );
+        assert_eq!(
+            assemble("quote 0xffffffffffffffff I"),
+            Err(AsmError {
+                line: 1,
+                kind: AsmErrorKind::QuoteTooLong
+            })
+        );
+        assert_eq!(assemble("quote 0xffffffffffffffff"), Ok(vec![]));
+        assert_eq!(
+            assemble("dna IC\nnat missing"),
+            Err(AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownSymbol("missing".into())
+            })
+        );
+        assert_eq!(
+            assemble("\n\nrule (I -> (0)"),
+            Err(AsmError {
+                line: 3,
+                kind: AsmErrorKind::InvalidNotation(NotationError::UnbalancedGroup { offset: 0 })
+            })
+        );
+        assert_eq!(
+            assemble("jump 1"),
+            Err(AsmError {
+                line: 1,
+                kind: AsmErrorKind::UnknownDirective("jump".into())
+            })
+        );
+        assert_eq!(
+            assemble("rule I"),
+            Err(AsmError {
+                line: 1,
+                kind: AsmErrorKind::MissingArrow
+            })
+        );
+        assert_eq!(
+            assemble("a:\nconst b a"),
+            Err(AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownSymbol("a".into())
+            })
+        );
+        for label in ["foo bar", "", "IIC", "a.b", "1a"] {
+            assert_eq!(
+                assemble(&format!("dna I\n{}:", label)),
+                Err(AsmError {
+                    line: 2,
+                    kind: AsmErrorKind::InvalidLabel(label.into())
+                })
+            );
+        }
+        assert_eq!(
+            assemble("a:\nconst a 1"),
+            Err(AsmError {
+                line: 2,
+                kind: AsmErrorKind::DuplicateSymbol("a".into())
+            })
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use super::{Dna, DnaRope, NotationError, Pattern, Step, pattern};
+use crate::rna::Rna;
+
+pub type BreakpointId = usize;
+
+/// Condition checked after every iteration of [`super::DnaExecutor`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// The given RNA command has been emitted.
+    Rna(Rna),
+    /// The number of emitted RNA commands has reached the given count.
+    RnaCount(usize),
+    /// The decoded pattern is equal to the given one.
+    Pattern(Vec<Pattern>),
+    /// The DNA length has crossed the given threshold in either direction.
+    DnaLength(usize),
+    /// The remaining DNA starts with the given bases.
+    DnaPrefix(Vec<Dna>),
+}
+
+impl Breakpoint {
+    /// Creates a [`Breakpoint::Pattern`] from textual notation, e.g. `(?[IFPP])F`.
+    pub fn pattern(text: &str) -> Result<Self, NotationError> {
+        Ok(Breakpoint::Pattern(pattern::parse(text)?))
+    }
+
+    /// Checks the breakpoint against `step`, `rna_count` and `dna_len` are
+    /// the values before the step.
+    pub(super) fn hit(&self, step: &Step, rna_count: usize, dna_len: usize, dna: &DnaRope) -> bool {
+        match self {
+            Breakpoint::Rna(rna) => step.rna.contains(rna),
+            Breakpoint::RnaCount(count) => {
+                rna_count < *count && rna_count + step.rna.len() >= *count
+            }
+            Breakpoint::Pattern(pattern) => &step.pattern == pattern,
+            Breakpoint::DnaLength(threshold) => (dna_len < *threshold) != (dna.len() < *threshold),
+            Breakpoint::DnaPrefix(prefix) => dna.iter().skip_prefix(prefix),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DnaExecutor, ExecutionStatus, assemble, read_dna};
+    use super::*;
+
+    const SOURCE: &str = "
+        rna PIIIIIP
+        rule ->
+        rna PIIIIIP
+        rule ->
+        rna PIIIIIP
+        rule ->
+        rule FPCI ->
+    ";
+
+    fn executor() -> DnaExecutor {
+        DnaExecutor::from_rope(DnaRope::from(assemble(SOURCE).unwrap()))
+    }
+
+    #[test]
+    fn rna() {
+        let mut dna_executor = executor();
+        let id = dna_executor.add_breakpoint(Breakpoint::Rna(Rna::Move));
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::Breakpoint(id)
+        );
+        assert_eq!(dna_executor.loops_count(), 1);
+        assert!(dna_executor.remove_breakpoint(id));
+        assert!(!dna_executor.remove_breakpoint(id));
+        assert_eq!(dna_executor.execute_loops(1), ExecutionStatus::Paused);
+        assert_eq!(dna_executor.execute_loops(10), ExecutionStatus::Finished);
+        assert_eq!(dna_executor.loops_count(), 5);
+    }
+
+    #[test]
+    fn rna_count() {
+        let mut dna_executor = executor();
+        dna_executor.add_breakpoint(Breakpoint::RnaCount(5));
+        let id = dna_executor.add_breakpoint(Breakpoint::RnaCount(2));
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::Breakpoint(id)
+        );
+        assert_eq!(dna_executor.rna().len(), 2);
+    }
+
+    #[test]
+    fn pattern() {
+        let mut dna_executor = executor();
+        let id = dna_executor.add_breakpoint(Breakpoint::pattern("FPCI").unwrap());
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::Breakpoint(id)
+        );
+        assert_eq!(dna_executor.loops_count(), 4);
+        assert!(Breakpoint::pattern("(").is_err());
+    }
+
+    #[test]
+    fn dna_length_and_prefix() {
+        let mut dna_executor = executor();
+        let threshold = dna_executor.dna_len() - 10;
+        let id = dna_executor.add_breakpoint(Breakpoint::DnaLength(threshold));
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::Breakpoint(id)
+        );
+        assert_eq!(dna_executor.loops_count(), 1);
+
+        dna_executor.clear_breakpoints();
+        let id = dna_executor.add_breakpoint(Breakpoint::DnaPrefix(read_dna("PICFC")));
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::Breakpoint(id)
+        );
+        assert_eq!(dna_executor.loops_count(), 3);
+    }
+}
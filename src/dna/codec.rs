@@ -0,0 +1,126 @@
+//! Helpers for the binary trace and snapshot formats.
+
+use super::Dna::{self, *};
+use crate::rna::Rna;
+
+use std::io::{self, Read, Write};
+
+const BASES: [Dna; 4] = [I, C, F, P];
+
+pub fn write_varint<W: Write>(writer: &mut W, mut n: u64) -> io::Result<()> {
+    let mut buf = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buf[..len])
+}
+
+pub fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        // The tenth byte holds the last bit of a 64-bit number.
+        if shift >= 64 || (shift == 63 && byte[0] & 0x7e != 0) {
+            return Err(invalid_data("varint is too long"));
+        }
+        n |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+    }
+}
+
+pub fn write_usize<W: Write>(writer: &mut W, n: usize) -> io::Result<()> {
+    write_varint(writer, n as u64)
+}
+
+pub fn read_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_varint(reader)?).map_err(|_| invalid_data("number does not fit usize"))
+}
+
+/// Packs bases four per byte, the first base in the lowest bits.
+pub fn pack<'a, I: IntoIterator<Item = &'a Dna>>(dna: I) -> Vec<u8> {
+    let mut packed = vec![];
+    for (index, base) in dna.into_iter().enumerate() {
+        if index % 4 == 0 {
+            packed.push(0);
+        }
+        *packed.last_mut().unwrap() |= (*base as u8) << (2 * (index % 4));
+    }
+    packed
+}
+
+pub fn unpack(packed: &[u8], len: usize) -> Vec<Dna> {
+    (0..len)
+        .map(|index| BASES[usize::from(packed[index / 4] >> (2 * (index % 4))) & 3])
+        .collect()
+}
+
+/// Writes the number of bases followed by the packed bases.
+pub fn write_dna<W: Write>(writer: &mut W, dna: &[Dna]) -> io::Result<()> {
+    write_usize(writer, dna.len())?;
+    writer.write_all(&pack(dna))
+}
+
+pub fn read_dna<R: Read>(reader: &mut R) -> io::Result<Vec<Dna>> {
+    let len = read_usize(reader)?;
+    let bytes = len.div_ceil(4);
+    // Read through `take` so a corrupted length fails instead of allocating.
+    let mut packed = vec![];
+    reader.take(bytes as u64).read_to_end(&mut packed)?;
+    if packed.len() != bytes {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(unpack(&packed, len))
+}
+
+pub fn write_rna<W: Write>(writer: &mut W, rna: &Rna) -> io::Result<()> {
+    write_dna(writer, &rna.to_dna())
+}
+
+pub fn read_rna<R: Read>(reader: &mut R) -> io::Result<Rna> {
+    Ok(Rna::from_dna(&read_dna(reader)?))
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint() {
+        for n in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buf = vec![];
+            write_varint(&mut buf, n).unwrap();
+            assert_eq!(read_varint(&mut &buf[..]).unwrap(), n);
+        }
+        assert!(read_varint(&mut &[0xff; 11][..]).is_err());
+        let mut overflow = [0xff; 10];
+        overflow[9] = 0x02;
+        assert!(read_varint(&mut &overflow[..]).is_err());
+    }
+
+    #[test]
+    fn packed_dna() {
+        let dna = vec![I, C, F, P, P, F, C];
+        assert_eq!(pack(&dna), vec![0b11_10_01_00, 0b00_01_10_11]);
+        let mut buf = vec![];
+        write_dna(&mut buf, &dna).unwrap();
+        assert_eq!(read_dna(&mut &buf[..]).unwrap(), dna);
+    }
+}
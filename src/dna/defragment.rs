@@ -0,0 +1,120 @@
+use super::DnaRope;
+
+use std::time::{Duration, Instant};
+
+/// When a [`super::DnaExecutor`] compacts the DNA rope after an iteration.
+///
+/// Defaults to `Never`: the balanced rope keeps splits and seeks logarithmic
+/// in its chunk count, so the periodic copy of the whole genome the flat rope
+/// needed is no longer worth its cost.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum DefragmentPolicy {
+    #[default]
+    Never,
+    /// Copies the DNA into a single chunk every given number of iterations.
+    EveryIterations(usize),
+    /// Copies the DNA into a single chunk once it is stored in more chunks
+    /// than given.
+    RopeCountAbove(usize),
+    /// Merges runs of chunks shorter than `min_len` once the DNA is stored in
+    /// more than `rope_count` chunks, see [`DnaRope::merge_small_chunks`].
+    MergeSmallChunks { rope_count: usize, min_len: usize },
+}
+
+/// Totals over every defragmentation done by a [`super::DnaExecutor`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DefragmentStats {
+    pub runs: usize,
+    /// Sum of the chunk counts before every run.
+    pub chunks_before: usize,
+    /// Sum of the chunk counts after every run.
+    pub chunks_after: usize,
+    pub bases_copied: usize,
+    pub time: Duration,
+}
+
+impl DefragmentPolicy {
+    fn is_due(&self, loops_count: usize, rope_count: usize) -> bool {
+        match *self {
+            DefragmentPolicy::Never => false,
+            DefragmentPolicy::EveryIterations(n) => n > 0 && loops_count.is_multiple_of(n),
+            DefragmentPolicy::RopeCountAbove(n)
+            | DefragmentPolicy::MergeSmallChunks { rope_count: n, .. } => rope_count > n,
+        }
+    }
+
+    /// Defragments `dna` after iteration `loops_count` if the policy says so.
+    pub(super) fn apply(
+        &self,
+        dna: DnaRope,
+        loops_count: usize,
+        stats: &mut DefragmentStats,
+    ) -> DnaRope {
+        let rope_count = dna.rope_count();
+        if !self.is_due(loops_count, rope_count) {
+            return dna;
+        }
+        let start = Instant::now();
+        let (dna, copied) = match *self {
+            DefragmentPolicy::MergeSmallChunks { min_len, .. } => dna.merge_small_chunks(min_len),
+            _ => {
+                let len = dna.len();
+                (dna.defragment(), len)
+            }
+        };
+        debug!(
+            "defragmented {} chunks into {} copying {} bases",
+            rope_count,
+            dna.rope_count(),
+            copied
+        );
+        stats.runs += 1;
+        stats.chunks_before += rope_count;
+        stats.chunks_after += dna.rope_count();
+        stats.bases_copied += copied;
+        stats.time += start.elapsed();
+        dna
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Dna::*;
+    use super::*;
+
+    fn sample_dna() -> DnaRope {
+        DnaRope::from_raw(vec![vec![I; 3], vec![C; 400], vec![F; 2], vec![P; 2]])
+    }
+
+    fn apply(policy: DefragmentPolicy, loops_count: usize) -> (usize, DefragmentStats) {
+        let mut stats = DefragmentStats::default();
+        let dna = policy.apply(sample_dna(), loops_count, &mut stats);
+        assert_eq!(dna.to_string(), sample_dna().to_string());
+        (dna.rope_count(), stats)
+    }
+
+    #[test]
+    fn policies() {
+        assert_eq!(apply(DefragmentPolicy::Never, 1).0, 4);
+        assert_eq!(apply(DefragmentPolicy::EveryIterations(0), 0).0, 4);
+        assert_eq!(apply(DefragmentPolicy::EveryIterations(3), 4).0, 4);
+        assert_eq!(apply(DefragmentPolicy::RopeCountAbove(4), 1).0, 4);
+
+        let (rope_count, stats) = apply(DefragmentPolicy::EveryIterations(3), 6);
+        assert_eq!(rope_count, 1);
+        assert_eq!(
+            (stats.runs, stats.chunks_before, stats.chunks_after),
+            (1, 4, 1)
+        );
+        assert_eq!(stats.bases_copied, 407);
+        assert_eq!(apply(DefragmentPolicy::RopeCountAbove(3), 1).0, 1);
+
+        let policy = DefragmentPolicy::MergeSmallChunks {
+            rope_count: 3,
+            min_len: 4,
+        };
+        let (rope_count, stats) = apply(policy, 1);
+        assert_eq!(rope_count, 3);
+        assert_eq!(stats.bases_copied, 4);
+    }
+}
@@ -0,0 +1,201 @@
+use super::{DnaRope, DnaRopeIter, Pattern, RnaSink, Template, debug, pattern, template};
+use crate::rna::{RNA_LEN, Rna};
+
+use std::collections::VecDeque;
+use std::fmt;
+
+const TAIL_PREVIEW: usize = 32;
+
+/// A decoded piece of DNA, see [`disassemble`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    /// A pattern and a template, from `offset` to the end of the template.
+    /// RNA commands before the first pattern base are not part of the rule.
+    Rule {
+        offset: usize,
+        len: usize,
+        pattern: Vec<Pattern>,
+        template: Vec<Template>,
+    },
+    /// A command emitted while decoding, listed at the offset of its `III`.
+    Rna { offset: usize, rna: Rna },
+    /// Trailing DNA which does not decode into a complete rule, sharing its
+    /// chunks with the disassembled rope.
+    Tail { offset: usize, dna: DnaRope },
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Rule {
+                offset,
+                pattern,
+                template,
+                ..
+            } => write!(f, "{:8} {} -> {}", offset, debug(pattern), debug(template)),
+            Instruction::Rna { offset, rna } => write!(f, "{:8} rna {:?}", offset, rna),
+            Instruction::Tail { offset, dna } => write!(
+                f,
+                "{:8} tail {}{} ({} bases)",
+                offset,
+                dna.slice(0..dna.len().min(TAIL_PREVIEW)),
+                if dna.len() > TAIL_PREVIEW { "..." } else { "" },
+                dna.len()
+            ),
+        }
+    }
+}
+
+/// RNA commands with their offsets.
+struct Commands(Vec<(usize, Rna)>);
+
+impl RnaSink for Commands {
+    fn emit(&mut self, offset: usize, rna: Rna) {
+        self.0.push((offset, rna));
+    }
+}
+
+/// Iterator decoding consecutive pattern/template pairs without performing
+/// the match and replace step, in the order of their offsets.
+pub struct Disassembler<'a> {
+    dna: &'a DnaRope,
+    iter: DnaRopeIter<'a>,
+    finished: bool,
+    /// Instructions decoded along with the last rule or tail.
+    pending: VecDeque<Instruction>,
+}
+
+impl Disassembler<'_> {
+    /// Queues the next rule or the tail with the RNA met while decoding it.
+    fn decode(&mut self) {
+        let offset = self.iter.pos();
+        let mut rna = Commands(vec![]);
+        let pattern = pattern::execute(&mut rna, &mut self.iter);
+        let template = pattern
+            .as_ref()
+            .and_then(|_| template::execute(&mut rna, &mut self.iter));
+        let mut rna = rna.0.into_iter().peekable();
+        let instruction = match (pattern, template) {
+            (Some(pattern), Some(template)) => {
+                // The rule starts after the commands leading its pattern.
+                let mut start = offset;
+                while let Some((offset, rna)) = rna.next_if(|x| x.0 == start) {
+                    self.pending.push_back(Instruction::Rna { offset, rna });
+                    start += 3 + RNA_LEN;
+                }
+                Instruction::Rule {
+                    offset: start,
+                    len: self.iter.pos() - start,
+                    pattern,
+                    template,
+                }
+            }
+            _ => {
+                self.finished = true;
+                Instruction::Tail {
+                    offset,
+                    dna: self.dna.slice(offset..self.dna.len()),
+                }
+            }
+        };
+        self.pending.push_back(instruction);
+        self.pending
+            .extend(rna.map(|(offset, rna)| Instruction::Rna { offset, rna }));
+    }
+}
+
+impl Iterator for Disassembler<'_> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() && !self.finished && self.iter.pos() != self.dna.len() {
+            self.decode();
+        }
+        self.pending.pop_front()
+    }
+}
+
+pub fn disassemble(dna: &DnaRope) -> Disassembler<'_> {
+    Disassembler {
+        dna,
+        iter: dna.iter(),
+        finished: false,
+        pending: VecDeque::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Dna::*, assemble, read_dna};
+    use super::*;
+
+    #[test]
+    fn rules_and_tail() {
+        let source = "
+            rule (?[IFPP])F -> (0)P
+            rna PIIIIIP
+            rule ![3]I -> |0|
+            dna IIPIP
+        ";
+        let dna = DnaRope::from(assemble(source).unwrap());
+        let listing: Vec<_> = disassemble(&dna).collect();
+        assert_eq!(listing.len(), 4);
+        assert_eq!(
+            listing[0],
+            Instruction::Rule {
+                offset: 0,
+                len: 28,
+                pattern: pattern::parse("(?[IFPP])F").unwrap(),
+                template: template::parse("(0)P").unwrap(),
+            }
+        );
+        assert_eq!(listing[1].to_string(), "      28 rna Move");
+        assert_eq!(listing[2].to_string(), "      38 ![3]I -> |0|");
+        assert_eq!(
+            listing[3],
+            Instruction::Tail {
+                offset: 54,
+                dna: DnaRope::from(vec![I, I, P, I, P]),
+            }
+        );
+        assert_eq!(listing[3].to_string(), "      54 tail IIPIP (5 bases)");
+    }
+
+    #[test]
+    fn inline_rna() {
+        // RNA inside a pattern, then in a tail.
+        let dna = DnaRope::from(read_dna("CIIIPIIIIIPIICIICIIIPIIIIIPC"));
+        let listing: Vec<_> = disassemble(&dna).map(|x| x.to_string()).collect();
+        assert_eq!(
+            listing,
+            [
+                "       0 I -> ",
+                "       1 rna Move",
+                "      17 tail IIIPIIIIIPC (11 bases)",
+                "      17 rna Move",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_tail() {
+        let text = format!("IIPIP{}", "C".repeat(40));
+        let dna = DnaRope::from(read_dna(&text));
+        let listing: Vec<_> = disassemble(&dna).collect();
+        assert_eq!(listing.len(), 1);
+        let Instruction::Tail { dna: tail, .. } = &listing[0] else {
+            panic!("expected a tail: {:?}", listing[0]);
+        };
+        assert_eq!(tail, &dna);
+        assert_eq!(
+            listing[0].to_string(),
+            format!("       0 tail {}... (45 bases)", &text[..32])
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let dna = DnaRope::from(read_dna(""));
+        assert_eq!(disassemble(&dna).next(), None);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Instant;
+
+/// Bounds on the execution of a [`super::DnaExecutor`], unset fields are
+/// unlimited.
+///
+/// An iteration which would exceed a limit is undone, so the executor can be
+/// inspected or resumed with other limits afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionLimits {
+    /// Total number of iterations, counted by [`super::DnaExecutor::loops_count`].
+    pub max_iterations: Option<usize>,
+    pub max_dna_len: Option<usize>,
+    pub max_rna: Option<usize>,
+    /// Checked before every iteration, e.g. `Instant::now() + timeout`.
+    pub deadline: Option<Instant>,
+    /// Number of bases a single template may expand to.
+    pub max_template_expansion: Option<usize>,
+}
+
+/// The limit of [`ExecutionLimits`] which stopped execution.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Limit {
+    Iterations,
+    DnaLength,
+    RnaCount,
+    Deadline,
+    TemplateExpansion,
+}
+
+impl ExecutionLimits {
+    /// Returns the limit preventing the next iteration from starting.
+    pub(super) fn before_step(&self, loops_count: usize) -> Option<Limit> {
+        if self.max_iterations.is_some_and(|x| loops_count >= x) {
+            return Some(Limit::Iterations);
+        }
+        if self.deadline.is_some_and(|x| Instant::now() >= x) {
+            return Some(Limit::Deadline);
+        }
+        None
+    }
+}
@@ -0,0 +1,153 @@
+use super::{DnaExecutor, DnaParser, DnaRope, ParseError, ParseMode};
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "i/o error: {}", e),
+            LoadError::Parse(e) => write!(f, "parse error: {}", e),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(value: io::Error) -> Self {
+        LoadError::Io(value)
+    }
+}
+
+impl From<ParseError> for LoadError {
+    fn from(value: ParseError) -> Self {
+        LoadError::Parse(value)
+    }
+}
+
+fn invalid_utf8(offset: usize) -> LoadError {
+    LoadError::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("stream did not contain valid UTF-8 at offset {}", offset),
+    ))
+}
+
+impl DnaRope {
+    /// Reads DNA from `reader`, appending every chunk read to the rope.
+    pub fn from_reader<R: Read>(mut reader: R, mode: ParseMode) -> Result<Self, LoadError> {
+        let mut parser = DnaParser::new(mode);
+        let mut rope = DnaRope::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut pending = 0;
+        let mut offset = 0;
+        loop {
+            let read = match reader.read(&mut buf[pending..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let filled = pending + read;
+            let valid = match std::str::from_utf8(&buf[..filled]) {
+                Ok(text) => text.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(invalid_utf8(offset + e.valid_up_to())),
+            };
+            let text = std::str::from_utf8(&buf[..valid]).unwrap();
+            let mut dna = Vec::with_capacity(valid);
+            parser.feed(text, &mut dna)?;
+            if !dna.is_empty() {
+                rope.append_dna(dna);
+            }
+            buf.copy_within(valid..filled, 0);
+            pending = filled - valid;
+            offset += valid;
+        }
+        if pending > 0 {
+            return Err(invalid_utf8(offset));
+        }
+        Ok(rope)
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P, mode: ParseMode) -> Result<Self, LoadError> {
+        Self::from_reader(File::open(path)?, mode)
+    }
+}
+
+impl DnaExecutor {
+    pub fn from_reader<R: Read>(reader: R, mode: ParseMode) -> Result<Self, LoadError> {
+        Ok(Self::from_rope(DnaRope::from_reader(reader, mode)?))
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P, mode: ParseMode) -> Result<Self, LoadError> {
+        Ok(Self::from_rope(DnaRope::from_path(path, mode)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Dna::*;
+    use super::*;
+
+    /// Hands out the data one byte at a time to exercise chunk boundaries.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn from_reader() {
+        let rope = DnaRope::from_reader("ICFP".as_bytes(), ParseMode::Strict).unwrap();
+        assert_eq!(rope.as_vec(), vec![&I, &C, &F, &P]);
+
+        let prefix = "IIP # ü\n".as_bytes();
+        let rope =
+            DnaRope::from_reader(ByteReader(prefix).chain(&b"CF"[..]), ParseMode::Lenient).unwrap();
+        assert_eq!(rope.as_vec(), vec![&I, &I, &P, &C, &F]);
+        assert_eq!(rope.rope_count(), 1);
+    }
+
+    #[test]
+    fn from_reader_errors() {
+        match DnaRope::from_reader(ByteReader("IIPé".as_bytes()), ParseMode::Strict) {
+            Err(LoadError::Parse(ParseError::UnknownSymbol { symbol, offset, .. })) => {
+                assert_eq!(symbol, 'é');
+                assert_eq!(offset, 3);
+            }
+            _ => panic!("expected parse error"),
+        }
+        match DnaRope::from_reader(&b"IIP\xc3"[..], ParseMode::Strict) {
+            Err(LoadError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            _ => panic!("expected i/o error"),
+        }
+    }
+}
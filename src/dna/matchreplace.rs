@@ -1,43 +1,86 @@
 use super::{
     Dna::{self, *},
-    DnaRope,
+    DnaRope, ExecutionStatus,
+    limits::Limit,
     pattern::Pattern,
+    search::SearchCache,
     template::Template,
 };
 
 use std::ops::Range;
 
-pub fn execute(pattern: Vec<Pattern>, template: Vec<Template>, dna: DnaRope) -> DnaRope {
-    if let Some((pos, ref env)) = execute_match(pattern, &dna) {
-        execute_replace(&template, pos, env, dna)
-    } else {
-        dna
-    }
+/// Protected captures at least this long are decoded lazily, see [`DnaRope::quoted`].
+const LAZY_PROTECT_LEN: usize = 256;
+
+/// Position right after the matched DNA along with the captured environment.
+pub type Match = (usize, Vec<Range<usize>>);
+
+/// Matches `pattern` against `dna` starting at `start` and replaces the
+/// matched part using `template`. Returns the DNA following `start` after the
+/// replacement along with the match end position and environment relative to
+/// `start`, if the match succeeded.
+///
+/// The result is spliced from slices of `dna`, only bases produced by the
+/// template itself are allocated. Fails with [`ExecutionStatus::Overflow`] if
+/// the result would be longer than `usize::MAX` bases, or with
+/// [`Limit::TemplateExpansion`] if the template expands to more than
+/// `max_expansion` bases.
+pub fn execute(
+    pattern: &[Pattern],
+    template: &[Template],
+    dna: &DnaRope,
+    start: usize,
+    searches: &mut SearchCache,
+    max_expansion: usize,
+) -> Result<(DnaRope, Option<Match>), ExecutionStatus> {
+    let Some((pos, env)) = execute_match(pattern, dna, start, searches) else {
+        return Ok((dna.slice(start..dna.len()), None));
+    };
+    let mut result = execute_replace(template, &env, dna, max_expansion)?;
+    check_len(result.len(), dna.len() - pos, usize::MAX)?;
+    result.append(dna.slice(pos..dna.len()));
+    let env = env.into_iter().map(|x| x.start - start..x.end - start);
+    Ok((result, Some((pos - start, env.collect()))))
 }
 
-fn execute_match(pattern: Vec<Pattern>, dna: &DnaRope) -> Option<(usize, Vec<Range<usize>>)> {
+fn execute_match(
+    pattern: &[Pattern],
+    dna: &DnaRope,
+    start: usize,
+    searches: &mut SearchCache,
+) -> Option<Match> {
     let mut e: Vec<Range<usize>> = vec![];
     let mut c: Vec<usize> = vec![];
     let mut iter = dna.iter();
-    for p in pattern {
+    dna.iter_seek(&mut iter, start);
+    let mut bases = vec![];
+    for (index, p) in pattern.iter().enumerate() {
         match p {
-            Pattern::Base(c) => match iter.next() {
-                Some(dnac) if dnac == &c => (),
-                _ => return None,
-            },
+            Pattern::Base(c) => {
+                // Runs of bases are compared at once by `skip_prefix`.
+                bases.push(*c);
+                if let Some(Pattern::Base(_)) = pattern.get(index + 1) {
+                    continue;
+                }
+                if !iter.skip_prefix(&bases) {
+                    return None;
+                }
+                bases.clear();
+            }
             Pattern::Skip(n) => {
-                let absolute_pos = iter.pos() + n;
+                let absolute_pos = iter.pos().checked_add(*n)?;
                 if absolute_pos > dna.len() {
                     return None;
                 }
                 dna.iter_seek(&mut iter, absolute_pos);
             }
+            Pattern::SkipOverflow(_) => return None,
             Pattern::Search(c) => {
                 if !c.is_empty() {
                     if dna.len() - iter.pos() < c.len() {
                         return None;
                     }
-                    if let Some(n) = iter.dna_search(&c) {
+                    if let Some(n) = searches.get(c).find(&iter) {
                         dna.iter_seek(&mut iter, n + c.len());
                     } else {
                         return None;
@@ -58,72 +101,81 @@ fn execute_match(pattern: Vec<Pattern>, dna: &DnaRope) -> Option<(usize, Vec<Ran
     Some((iter.pos(), e))
 }
 
+/// Expands `template` with the environment `e` given as ranges of `dna`
+/// into at most `max_len` bases.
 fn execute_replace(
     template: &[Template],
-    pos: usize,
     e: &[Range<usize>],
-    mut dna: DnaRope,
-) -> DnaRope {
-    let mut postfix = dna.split_off(pos);
-
-    let mut prefix = DnaRope::new();
-    let mut env = vec![];
-    for t in template {
-        if let Template::NumberLevel(n, _) = t
-            && let Some(env_n) = e.get(*n)
-            && env_n.start != env_n.end
-        {
-            env.push(env_n.clone());
-        }
-    }
-    let splitted_env = dna.split_by_ranges(&env);
-    let mut env = splitted_env.into_iter();
+    dna: &DnaRope,
+    max_len: usize,
+) -> Result<DnaRope, ExecutionStatus> {
+    let mut result = DnaRope::new();
     let mut r = vec![];
     for t in template {
         match t {
             Template::Base(gene) => r.push(*gene),
             Template::NumberLevel(n, l) => {
-                if let Some(e_original) = e.get(*n)
-                    && e_original.start != e_original.end
+                if let Some(env_n) = e.get(*n)
+                    && env_n.start != env_n.end
                 {
-                    let env_n = env.next().unwrap();
-                    if *l == 0 {
+                    let env_n = dna.slice(env_n.clone());
+                    let quoted = DnaRope::checked_quoted(env_n.clone(), *l)
+                        .ok_or(ExecutionStatus::Overflow)?;
+                    if *l > 0 && quoted.len() < LAZY_PROTECT_LEN {
+                        r.extend(protect(*l, env_n.iter()));
+                    } else {
                         if !r.is_empty() {
-                            prefix.append_dna(std::mem::take(&mut r));
+                            result.append_dna(std::mem::take(&mut r));
                         }
-                        prefix.append(env_n);
-                    } else {
-                        r.extend(protect(*l, &env_n.iter().cloned().collect::<Vec<_>>()[..]));
+                        check_len(result.len(), quoted.len(), max_len)?;
+                        result.append(quoted);
                     }
                 }
             }
+            Template::NumberLevelOverflow(n, _) => {
+                // Only the level can overflow for a group that exists.
+                if let Some(env_n) = n.to_usize().and_then(|n| e.get(n))
+                    && env_n.start != env_n.end
+                {
+                    return Err(ExecutionStatus::Overflow);
+                }
+            }
             Template::Length(n) => {
                 r.extend(asnat(e.get(*n).map(|x| x.len()).unwrap_or(0)));
             }
+            Template::LengthOverflow(_) => r.extend(asnat(0)),
         }
+        check_len(result.len(), r.len(), max_len)?;
     }
     if !r.is_empty() {
-        prefix.append_dna(r);
+        result.append_dna(r);
+    }
+    Ok(result)
+}
+
+/// Checks that `len + extra` neither overflows nor exceeds `max_len`.
+fn check_len(len: usize, extra: usize, max_len: usize) -> Result<(), ExecutionStatus> {
+    match len.checked_add(extra) {
+        None => Err(ExecutionStatus::Overflow),
+        Some(len) if len > max_len => Err(ExecutionStatus::LimitReached(Limit::TemplateExpansion)),
+        Some(_) => Ok(()),
     }
-    postfix.prepend(prefix);
-    postfix
 }
 
-fn protect(lvl: usize, gene: &[Dna]) -> Vec<Dna> {
+pub fn protect<'a, T: IntoIterator<Item = &'a Dna>>(lvl: usize, gene: T) -> Vec<Dna> {
     if lvl == 0 {
-        return gene.to_vec();
+        return gene.into_iter().cloned().collect();
     }
-    let mut d = gene.to_vec();
-    let mut count = lvl;
-    while count > 0 {
-        d = quote(d);
-        count -= 1;
+    let mut d = quote(gene);
+    for _ in 1..lvl {
+        d = quote(&d);
     }
     d
 }
 
-fn quote(gene: Vec<Dna>) -> Vec<Dna> {
-    let mut res = Vec::with_capacity(gene.len());
+pub fn quote<'a, T: IntoIterator<Item = &'a Dna>>(gene: T) -> Vec<Dna> {
+    let gene = gene.into_iter();
+    let mut res = Vec::with_capacity(gene.size_hint().0);
     for c in gene {
         match c {
             I => res.push(C),
@@ -138,11 +190,11 @@ fn quote(gene: Vec<Dna>) -> Vec<Dna> {
     res
 }
 
-fn asnat(n: usize) -> Vec<Dna> {
+pub fn asnat(n: usize) -> Vec<Dna> {
     let mut res = vec![];
     let mut n = n;
     while n != 0 {
-        res.push(if n % 2 == 0 { I } else { C });
+        res.push(if n.is_multiple_of(2) { I } else { C });
         n /= 2;
     }
     res.push(P);
@@ -1,14 +1,49 @@
+mod asm;
+mod breakpoint;
+mod codec;
+mod defragment;
+mod disasm;
+mod limits;
+mod load;
 mod matchreplace;
+mod nat;
+mod notation;
+mod observer;
+mod packed;
+mod parse;
 mod pattern;
 mod rope;
+mod search;
+mod snapshot;
 mod template;
+mod trace;
 
 use std::fmt;
+use std::io;
+use std::ops::Range;
 
+pub use self::asm::{AsmError, AsmErrorKind, assemble};
+pub use self::breakpoint::{Breakpoint, BreakpointId};
+pub use self::defragment::{DefragmentPolicy, DefragmentStats};
+pub use self::disasm::{Disassembler, Instruction, disassemble};
+pub use self::limits::{ExecutionLimits, Limit};
+pub use self::load::LoadError;
+pub use self::nat::{BigNat, Nat};
+pub use self::notation::NotationError;
+pub use self::observer::{CancellationToken, Observer, Progress};
+pub use self::parse::{DnaParser, ParseError, ParseMode, parse_dna};
+pub use self::pattern::{Pattern, encode as encode_pattern, parse as parse_pattern};
 pub use self::rope::{DnaRope, Iter as DnaRopeIter};
+use self::search::SearchCache;
+pub use self::search::Searcher;
+pub use self::template::{Template, encode as encode_template, parse as parse_template};
+pub use self::trace::{
+    BinaryTraceWriter, JsonTraceWriter, TraceEvent, TraceFilter, TraceReader, TraceSink,
+};
 use crate::rna::Rna;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[repr(u8)]
 pub enum Dna {
     I,
     C,
@@ -18,19 +53,16 @@ pub enum Dna {
 
 use self::Dna::*;
 
-#[derive(Debug)]
-pub enum ParseError {
-    UnknownSymbol(char),
-}
-
 impl Dna {
-    fn try_from(value: char) -> Result<Self, ParseError> {
+    /// Returns the base written as `value`, or `value` back if it is not
+    /// one. [`DnaParser`] reports where such a symbol occurs.
+    pub fn from_char(value: char) -> Result<Self, char> {
         match value {
             'I' => Ok(I),
             'C' => Ok(C),
             'F' => Ok(F),
             'P' => Ok(P),
-            _ => Err(ParseError::UnknownSymbol(value)),
+            _ => Err(value),
         }
     }
 }
@@ -41,60 +73,293 @@ impl fmt::Display for Dna {
     }
 }
 
+/// Parses DNA the tests know to be valid, see [`parse_dna`] otherwise.
+#[cfg(test)]
 pub fn read_dna(dna_str: &str) -> Vec<Dna> {
-    dna_str
-        .chars()
-        .map(Dna::try_from)
-        .map(Result::unwrap)
-        .collect()
+    parse_dna(dna_str, ParseMode::Strict).unwrap_or_else(|e| panic!("invalid DNA: {}", e))
 }
 
 pub struct DnaExecutor {
     dna: Option<DnaRope>,
     rna: Vec<Rna>,
     loops_count: usize,
+    tracer: Option<Box<dyn TraceSink>>,
+    trace_error: Option<io::Error>,
+    breakpoints: Vec<(BreakpointId, Breakpoint)>,
+    next_breakpoint_id: BreakpointId,
+    searches: SearchCache,
+    limits: ExecutionLimits,
+    observer: Option<(Box<dyn Observer>, usize)>,
+    cancellation: CancellationToken,
+    defragment: DefragmentPolicy,
+    defragment_stats: DefragmentStats,
+}
+
+/// Why [`DnaExecutor::execute_loops`] returned.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExecutionStatus {
+    /// The DNA can not be decoded any further.
+    Finished,
+    /// The requested number of iterations has been executed.
+    Paused,
+    /// The breakpoint with the given id fired during the last iteration.
+    Breakpoint(BreakpointId),
+    /// The next iteration would produce DNA longer than `usize::MAX` bases,
+    /// the executor is left as it was before that iteration.
+    Overflow,
+    /// Continuing would exceed the given limit, see [`ExecutionLimits`].
+    LimitReached(Limit),
+    /// The [`CancellationToken`] of the executor has been cancelled.
+    Cancelled,
+}
+
+impl ExecutionStatus {
+    pub fn is_finished(&self) -> bool {
+        *self == ExecutionStatus::Finished
+    }
 }
 
 impl DnaExecutor {
+    /// Executes until the DNA is exhausted or execution stops otherwise,
+    /// see [`ExecutionStatus`].
     pub fn execute(&mut self) -> &[Rna] {
-        while let Some(dna) = self.dna.take() {
-            let finished = self.execute_single(dna);
-            if finished {
-                break;
-            }
-        }
+        self.run(usize::MAX);
         self.rna()
     }
 
-    pub fn execute_loops(&mut self, loops: usize) -> bool {
-        let mut loops_done = 0;
-        while let Some(dna) = self.dna.take() {
-            let finished = self.execute_single(dna);
-            loops_done += 1;
-            if finished {
-                return true;
-            }
-            if loops_done == loops {
-                return false;
-            }
+    pub fn execute_loops(&mut self, loops: usize) -> ExecutionStatus {
+        self.run(loops)
+    }
+
+    /// Executes a single iteration, returning what happened during it or
+    /// why execution has finished or can not go on, see [`Self::execute_loops`].
+    pub fn step(&mut self) -> Result<Step, Stopped> {
+        let rna_start = self.rna.len();
+        match self.try_step(true) {
+            Ok(step) => Ok(step.expect("recorded step")),
+            Err(status) => Err(Stopped {
+                status,
+                rna: self.rna[rna_start..].to_vec(),
+            }),
         }
-        true
     }
 
     pub fn rna(&self) -> &[Rna] {
         &self.rna[..]
     }
 
-    fn add_rna(&mut self, rna: Rna) {
-        self.rna.push(rna);
+    /// Remaining DNA, `None` once execution has finished.
+    pub fn dna(&self) -> Option<&DnaRope> {
+        self.dna.as_ref()
     }
 
-    fn execute_single(&mut self, mut dna: DnaRope) -> bool {
-        self.loops_count += 1;
-        if self.loops_count % 60000 == 0 {
-            debug!("running defragment");
-            dna = dna.defragment();
+    pub fn dna_string(&self) -> String {
+        self.dna
+            .as_ref()
+            .map(DnaRope::to_string)
+            .unwrap_or_default()
+    }
+
+    pub fn dna_len(&self) -> usize {
+        self.dna.as_ref().map_or(0, DnaRope::len)
+    }
+
+    pub fn loops_count(&self) -> usize {
+        self.loops_count
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.dna.is_none()
+    }
+
+    /// Records every following iteration into `sink`. Tracing stops on the
+    /// first write error, which is then available from [`Self::trace_error`].
+    pub fn trace_to<T: TraceSink + 'static>(&mut self, sink: T) {
+        self.tracer = Some(Box::new(sink));
+        self.trace_error = None;
+    }
+
+    /// Stops tracing, flushing and returning the current sink.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn TraceSink>> {
+        let mut tracer = self.tracer.take()?;
+        if let Err(e) = tracer.flush() {
+            self.trace_error = Some(e);
+        }
+        Some(tracer)
+    }
+
+    pub fn trace_error(&self) -> Option<&io::Error> {
+        self.trace_error.as_ref()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|x| x.0 != id);
+        len != self.breakpoints.len()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints.iter().map(|x| (x.0, &x.1))
+    }
+
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    /// Reports progress to `observer` every `interval` iterations.
+    pub fn observe<T: Observer + 'static>(&mut self, observer: T, interval: usize) {
+        self.observer = Some((Box::new(observer), interval.max(1)));
+    }
+
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take().map(|x| x.0)
+    }
+
+    /// Returns a token stopping execution before the next iteration once
+    /// cancelled, from any thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Replaces the token checked before every iteration, e.g. to stop
+    /// several executors at once.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    pub fn set_defragment_policy(&mut self, policy: DefragmentPolicy) {
+        self.defragment = policy;
+    }
+
+    pub fn defragment_policy(&self) -> DefragmentPolicy {
+        self.defragment
+    }
+
+    pub fn defragment_stats(&self) -> &DefragmentStats {
+        &self.defragment_stats
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            iterations: self.loops_count,
+            dna_len: self.dna_len(),
+            rna_count: self.rna.len(),
+            rope_count: self.dna.as_ref().map_or(0, DnaRope::rope_count),
+        }
+    }
+
+    fn run(&mut self, loops: usize) -> ExecutionStatus {
+        let status = self.run_loops(loops);
+        if status != ExecutionStatus::Paused {
+            let progress = self.progress();
+            if let Some((observer, _)) = self.observer.as_mut() {
+                observer.stopped(status, &progress);
+            }
         }
+        status
+    }
+
+    fn run_loops(&mut self, loops: usize) -> ExecutionStatus {
+        for _ in 0..loops {
+            let rna_count = self.rna.len();
+            let dna_len = self.dna_len();
+            let step = match self.try_step(!self.breakpoints.is_empty()) {
+                Ok(step) => step,
+                Err(status) => return status,
+            };
+            if let Some(id) = step.and_then(|x| self.breakpoint_hit(&x, rna_count, dna_len)) {
+                return ExecutionStatus::Breakpoint(id);
+            }
+        }
+        ExecutionStatus::Paused
+    }
+
+    fn breakpoint_hit(
+        &self,
+        step: &Step,
+        rna_count: usize,
+        dna_len: usize,
+    ) -> Option<BreakpointId> {
+        let dna = self.dna.as_ref()?;
+        self.breakpoints
+            .iter()
+            .find(|x| x.1.hit(step, rna_count, dna_len, dna))
+            .map(|x| x.0)
+    }
+
+    fn trace(&mut self, step: Step) -> Step {
+        let Some(tracer) = self.tracer.as_mut() else {
+            return step;
+        };
+        let event = TraceEvent {
+            iteration: self.loops_count,
+            dna_len: self.dna.as_ref().map_or(0, DnaRope::len),
+            step,
+        };
+        if let Err(e) = tracer.record(&event) {
+            error!("trace stopped: {}", e);
+            self.tracer = None;
+            self.trace_error = Some(e);
+        }
+        event.step
+    }
+
+    /// Executes an iteration, returning its [`Step`] if `record` is set or
+    /// the iteration is traced.
+    fn try_step(&mut self, record: bool) -> Result<Option<Step>, ExecutionStatus> {
+        if self.dna.is_none() {
+            return Err(ExecutionStatus::Finished);
+        }
+        if self.cancellation.is_cancelled() {
+            return Err(ExecutionStatus::Cancelled);
+        }
+        if let Some(limit) = self.limits.before_step(self.loops_count) {
+            return Err(ExecutionStatus::LimitReached(limit));
+        }
+        let dna = self.dna.take().ok_or(ExecutionStatus::Finished)?;
+        let step = self.execute_single(dna, record)?;
+        if self
+            .observer
+            .as_ref()
+            .is_some_and(|x| self.loops_count.is_multiple_of(x.1))
+        {
+            let progress = self.progress();
+            if let Some((observer, _)) = self.observer.as_mut() {
+                observer.progress(&progress);
+            }
+        }
+        Ok(step)
+    }
+
+    /// Undoes the iteration which started with `dna` and `rna_len` RNA commands.
+    fn undo(&mut self, dna: DnaRope, rna_len: usize, status: ExecutionStatus) -> ExecutionStatus {
+        self.loops_count -= 1;
+        self.rna.truncate(rna_len);
+        self.dna = Some(dna);
+        status
+    }
+
+    fn execute_single(
+        &mut self,
+        dna: DnaRope,
+        record: bool,
+    ) -> Result<Option<Step>, ExecutionStatus> {
+        self.loops_count += 1;
         debug!(
             "running loop: {} dna len: {} dna ropes count: {} rna len: {}",
             self.loops_count,
@@ -102,29 +367,26 @@ impl DnaExecutor {
             dna.rope_count(),
             self.rna.len()
         );
+        let rna_start = self.rna.len();
         let (pattern, template, pos) = {
             let mut iter = dna.iter();
             let pos_pattern_start = iter.pos();
-            if let Some(pattern) = pattern::execute(self, &mut iter) {
-                let pos_pattern_end = iter.pos();
-                trace!(
-                    "pattern handled: {}",
-                    debug(&dna.get_range(pos_pattern_start..pos_pattern_end))
-                );
-                let pos_template_start = pos_pattern_end;
-                if let Some(template) = template::execute(self, &mut iter) {
-                    let pos_template_end = iter.pos();
-                    trace!(
-                        "template handled: {}",
-                        debug(&dna.get_range(pos_template_start..pos_template_end))
-                    );
-                    (pattern, template, iter.pos())
-                } else {
-                    return true;
-                }
-            } else {
-                return true;
-            }
+            let pattern =
+                pattern::execute(&mut self.rna, &mut iter).ok_or(ExecutionStatus::Finished)?;
+            let pos_pattern_end = iter.pos();
+            trace!(
+                "pattern handled: {}",
+                debug(&dna.get_range(pos_pattern_start..pos_pattern_end))
+            );
+            let pos_template_start = pos_pattern_end;
+            let template =
+                template::execute(&mut self.rna, &mut iter).ok_or(ExecutionStatus::Finished)?;
+            let pos_template_end = iter.pos();
+            trace!(
+                "template handled: {}",
+                debug(&dna.get_range(pos_template_start..pos_template_end))
+            );
+            (pattern, template, iter.pos())
         };
         let len = dna.len();
         trace!("dna len: {}", len);
@@ -134,13 +396,87 @@ impl DnaExecutor {
             debug(&template),
             pos
         );
-        dna = dna.split_off(pos);
-        let len = dna.len();
-        trace!("len {}", len);
-        self.dna = Some(matchreplace::execute(pattern, template, dna));
-        let len = self.dna.as_ref().map(|x| x.len()).unwrap();
-        trace!("{}", len);
-        false
+        if self.limits.max_rna.is_some_and(|x| self.rna.len() > x) {
+            return Err(self.undo(
+                dna,
+                rna_start,
+                ExecutionStatus::LimitReached(Limit::RnaCount),
+            ));
+        }
+        let max_expansion = self.limits.max_template_expansion.unwrap_or(usize::MAX);
+        let (next, matched) = match matchreplace::execute(
+            &pattern,
+            &template,
+            &dna,
+            pos,
+            &mut self.searches,
+            max_expansion,
+        ) {
+            Ok(result) => result,
+            Err(status) => {
+                warn!("loop {} stopped: {:?}", self.loops_count, status);
+                return Err(self.undo(dna, rna_start, status));
+            }
+        };
+        if self.limits.max_dna_len.is_some_and(|x| next.len() > x) {
+            return Err(self.undo(
+                dna,
+                rna_start,
+                ExecutionStatus::LimitReached(Limit::DnaLength),
+            ));
+        }
+        trace!("{}", next.len());
+        let next = self
+            .defragment
+            .apply(next, self.loops_count, &mut self.defragment_stats);
+        self.dna = Some(next);
+        if !record && self.tracer.is_none() {
+            return Ok(None);
+        }
+        let (position, env) = match matched {
+            Some((position, env)) => (Some(position), env),
+            None => (None, vec![]),
+        };
+        let step = Step {
+            pattern,
+            template,
+            prefix_len: pos,
+            position,
+            env,
+            rna: self.rna[rna_start..].to_vec(),
+        };
+        Ok(Some(self.trace(step)))
+    }
+}
+
+/// Why [`DnaExecutor::step`] did not execute an iteration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stopped {
+    pub status: ExecutionStatus,
+    /// RNA emitted while decoding a pattern or a template which ran out of
+    /// DNA, it is part of [`DnaExecutor::rna`] but of no [`Step`].
+    pub rna: Vec<Rna>,
+}
+
+/// Record of a single iteration of [`DnaExecutor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub pattern: Vec<Pattern>,
+    pub template: Vec<Template>,
+    /// Number of bases consumed by decoding the pattern and the template.
+    pub prefix_len: usize,
+    /// Position right after the matched part of the DNA remaining after the
+    /// decoded prefix, `None` if the pattern did not match.
+    pub position: Option<usize>,
+    /// Ranges captured by pattern groups, relative to the same DNA as `position`.
+    pub env: Vec<Range<usize>>,
+    /// RNA emitted while decoding the pattern and the template.
+    pub rna: Vec<Rna>,
+}
+
+impl Step {
+    pub fn matched(&self) -> bool {
+        self.position.is_some()
     }
 }
 
@@ -148,37 +484,75 @@ fn debug<T: ToString>(t: &[T]) -> String {
     t.iter().map(T::to_string).collect::<Vec<_>>().join("")
 }
 
-impl From<&str> for DnaExecutor {
-    fn from(value: &str) -> Self {
-        let dna = Some(DnaRope::from(read_dna(value)));
+impl DnaExecutor {
+    pub fn try_from_mode(value: &str, mode: ParseMode) -> Result<Self, ParseError> {
+        Ok(Self::from_rope(DnaRope::from(parse_dna(value, mode)?)))
+    }
+
+    pub fn from_rope(dna: DnaRope) -> Self {
         DnaExecutor {
-            dna,
+            dna: Some(dna),
             rna: vec![],
             loops_count: 0,
+            tracer: None,
+            trace_error: None,
+            breakpoints: vec![],
+            next_breakpoint_id: 0,
+            searches: SearchCache::default(),
+            limits: ExecutionLimits::default(),
+            observer: None,
+            cancellation: CancellationToken::new(),
+            defragment: DefragmentPolicy::default(),
+            defragment_stats: DefragmentStats::default(),
         }
     }
 }
 
-fn nat(dna_iter: &mut DnaRopeIter) -> Option<usize> {
+impl TryFrom<&str> for DnaExecutor {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, ParseError> {
+        Self::try_from_mode(value, ParseMode::Strict)
+    }
+}
+
+/// Receives the RNA commands met by the pattern and template decoders.
+trait RnaSink {
+    /// `offset` is the position of the `III` starting the command.
+    fn emit(&mut self, offset: usize, rna: Rna);
+}
+
+impl RnaSink for Vec<Rna> {
+    fn emit(&mut self, _: usize, rna: Rna) {
+        self.push(rna);
+    }
+}
+
+/// Decodes a number terminated by `P`, the least significant bit first.
+fn nat(dna_iter: &mut DnaRopeIter) -> Option<Nat> {
+    let mut words = vec![];
+    let mut word: u64 = 0;
     let mut bit_index = 0;
-    let mut n = 0;
     loop {
-        match dna_iter.next() {
-            Some(P) => {
-                return Some(n);
-            }
-            Some(I) | Some(F) => (),
-            Some(C) => {
-                if bit_index < 64 {
-                    n |= 1 << bit_index;
-                } else {
-                    n = usize::MAX;
-                }
-            }
-            _ => return None,
+        match dna_iter.next()? {
+            P => break,
+            I | F => (),
+            C => word |= 1 << bit_index,
         }
         bit_index += 1;
+        if bit_index == 64 {
+            words.push(word);
+            word = 0;
+            bit_index = 0;
+        }
+    }
+    if words.is_empty()
+        && let Ok(n) = usize::try_from(word)
+    {
+        return Some(Nat::Small(n));
     }
+    words.push(word);
+    Some(Nat::from_words(words))
 }
 
 #[cfg(test)]
@@ -186,14 +560,26 @@ mod tests {
 
     use super::*;
 
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Instant;
+
     #[test]
     fn case_read_dna() {
         assert_eq!(read_dna("ICFPC"), vec![I, C, F, P, C]);
     }
 
+    #[test]
+    fn case_try_from() {
+        assert!(DnaExecutor::try_from("ICFPC").is_ok());
+        assert!(DnaExecutor::try_from("ICFPC\n").is_err());
+        assert!(DnaExecutor::try_from_mode("ICF # prefix\nPC\n", ParseMode::Lenient).is_ok());
+    }
+
     fn check_e2e(source: &str, result: &str) {
         println!("dna {:?} result expected: {:?}", source, result);
-        let mut dna_executor = DnaExecutor::from(source);
+        let mut dna_executor = DnaExecutor::try_from(source).unwrap();
         dna_executor.execute_loops(1);
         let dna = dna_executor.dna.unwrap_or_else(|| DnaRope::from(vec![]));
         let dna_remainig = dna.as_vec();
@@ -217,4 +603,272 @@ mod tests {
     fn case_e2e_03() {
         check_e2e("IIPIPIICPIICIICCIICFCFC", "I");
     }
+
+    fn encoded_executor(pattern: &str, template: &str, rest: &str) -> DnaExecutor {
+        let mut dna = read_dna("IIIPIIIIIP");
+        dna.extend(encode_pattern(&parse_pattern(pattern).unwrap()));
+        dna.extend(encode_template(&parse_template(template).unwrap()));
+        dna.extend(read_dna(rest));
+        DnaExecutor::from_rope(DnaRope::from(dna))
+    }
+
+    #[test]
+    fn case_number_overflow() {
+        let big = "36893488147419103232";
+
+        let mut dna_executor = encoded_executor(&format!("(I)![{}]", big), "C", "ICFP");
+        let step = dna_executor.step().unwrap();
+        assert!(!step.matched());
+        assert_eq!(dna_executor.dna_string(), "ICFP");
+
+        let template = format!("({},3)|{}|(0)", big, big);
+        let mut dna_executor = encoded_executor("(I)", &template, "ICFP");
+        dna_executor.step().unwrap();
+        assert_eq!(dna_executor.dna_string(), "PICFP");
+
+        let template = format!("C(0,{})", big);
+        let mut dna_executor = encoded_executor("(I)", &template, "ICFP");
+        let dna = dna_executor.dna_string();
+        assert_eq!(dna_executor.execute_loops(3), ExecutionStatus::Overflow);
+        assert_eq!(
+            dna_executor.step().unwrap_err().status,
+            ExecutionStatus::Overflow
+        );
+        assert!(!dna_executor.is_finished());
+        assert_eq!(dna_executor.dna_string(), dna);
+        assert_eq!(dna_executor.loops_count(), 0);
+        assert!(dna_executor.rna().is_empty());
+
+        let mut dna_executor = encoded_executor("(I)", &template, "FCFP");
+        dna_executor.step().unwrap();
+        assert_eq!(dna_executor.dna_string(), "FCFP");
+    }
+
+    #[test]
+    fn case_limits() {
+        let dna = read_dna(&"IIIPIIIIIPIICIIC".repeat(10));
+        let mut dna_executor = DnaExecutor::from_rope(DnaRope::from(dna));
+        dna_executor.set_limits(ExecutionLimits {
+            max_iterations: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::LimitReached(Limit::Iterations)
+        );
+        assert_eq!(dna_executor.loops_count(), 3);
+
+        dna_executor.set_limits(ExecutionLimits {
+            max_rna: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::LimitReached(Limit::RnaCount)
+        );
+        assert_eq!(dna_executor.loops_count(), 5);
+        assert_eq!(dna_executor.rna().len(), 5);
+        assert_eq!(dna_executor.dna_len(), 16 * 5);
+
+        dna_executor.set_limits(ExecutionLimits {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        });
+        assert_eq!(
+            dna_executor.step().unwrap_err().status,
+            ExecutionStatus::LimitReached(Limit::Deadline)
+        );
+        assert_eq!(
+            dna_executor.execute_loops(10),
+            ExecutionStatus::LimitReached(Limit::Deadline)
+        );
+        assert_eq!(dna_executor.loops_count(), 5);
+
+        dna_executor.set_limits(ExecutionLimits::default());
+        assert_eq!(dna_executor.execute_loops(10), ExecutionStatus::Finished);
+        assert_eq!(dna_executor.rna().len(), 10);
+
+        let mut dna_executor = encoded_executor("(I)", "CCCCCCCC(0,2)", "ICFP");
+        let dna = dna_executor.dna_string();
+        dna_executor.set_limits(ExecutionLimits {
+            max_template_expansion: Some(8),
+            ..Default::default()
+        });
+        assert_eq!(
+            dna_executor.execute_loops(1),
+            ExecutionStatus::LimitReached(Limit::TemplateExpansion)
+        );
+        assert_eq!(dna_executor.dna_string(), dna);
+        assert!(dna_executor.rna().is_empty());
+
+        dna_executor.set_limits(ExecutionLimits {
+            max_template_expansion: Some(9),
+            max_dna_len: Some(11),
+            ..Default::default()
+        });
+        assert_eq!(
+            dna_executor.execute_loops(1),
+            ExecutionStatus::LimitReached(Limit::DnaLength)
+        );
+        assert_eq!(dna_executor.dna_string(), dna);
+
+        dna_executor.set_limits(ExecutionLimits {
+            max_template_expansion: Some(9),
+            max_dna_len: Some(12),
+            ..Default::default()
+        });
+        assert_eq!(dna_executor.execute_loops(1), ExecutionStatus::Paused);
+        assert_eq!(dna_executor.dna_string(), "CCCCCCCCFCFP");
+        assert_eq!(dna_executor.rna().len(), 1);
+    }
+
+    /// Progress reports, with the status for the final one of a run.
+    type Reports = Vec<(Option<ExecutionStatus>, Progress)>;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Reports>>);
+
+    impl Observer for Recorder {
+        fn progress(&mut self, progress: &Progress) {
+            self.0.borrow_mut().push((None, *progress));
+        }
+
+        fn stopped(&mut self, status: ExecutionStatus, progress: &Progress) {
+            self.0.borrow_mut().push((Some(status), *progress));
+        }
+    }
+
+    #[test]
+    fn case_observer() {
+        let dna = read_dna(&"IIIPIIIIIPIICIIC".repeat(10));
+        let mut dna_executor = DnaExecutor::from_rope(DnaRope::from(dna));
+        let recorder = Recorder::default();
+        dna_executor.observe(recorder.clone(), 4);
+        assert_eq!(dna_executor.execute_loops(9), ExecutionStatus::Paused);
+        dna_executor.execute();
+        let progress = |iterations, rna_count| Progress {
+            iterations,
+            dna_len: 16 * (10 - rna_count),
+            rna_count,
+            rope_count: if rna_count < 10 { 1 } else { 0 },
+        };
+        assert_eq!(
+            recorder.0.borrow()[..],
+            [
+                (None, progress(4, 4)),
+                (None, progress(8, 8)),
+                (Some(ExecutionStatus::Finished), progress(11, 10))
+            ]
+        );
+        assert!(dna_executor.take_observer().is_some());
+    }
+
+    #[test]
+    fn case_cancellation() {
+        let dna = read_dna(&"IIIPIIIIIPIICIIC".repeat(10));
+        let mut dna_executor = DnaExecutor::from_rope(DnaRope::from(dna));
+        assert_eq!(dna_executor.execute_loops(2), ExecutionStatus::Paused);
+        let token = dna_executor.cancellation_token();
+        thread::spawn(move || token.cancel()).join().unwrap();
+        assert_eq!(dna_executor.execute_loops(2), ExecutionStatus::Cancelled);
+        assert_eq!(
+            dna_executor.step().unwrap_err().status,
+            ExecutionStatus::Cancelled
+        );
+        assert_eq!(dna_executor.loops_count(), 2);
+
+        let token = CancellationToken::new();
+        dna_executor.set_cancellation_token(token.clone());
+        assert_eq!(dna_executor.execute_loops(2), ExecutionStatus::Paused);
+        token.cancel();
+        assert_eq!(dna_executor.execute_loops(2), ExecutionStatus::Cancelled);
+        token.reset();
+        assert_eq!(dna_executor.execute_loops(20), ExecutionStatus::Finished);
+        assert_eq!(dna_executor.rna().len(), 10);
+    }
+
+    #[test]
+    fn case_defragment_policy() {
+        let dna = read_dna(&"IIIPIIIIIPIICIIC".repeat(10));
+        let dna = DnaRope::from_raw(dna.chunks(16).map(|x| x.to_vec()).collect());
+        let mut dna_executor = DnaExecutor::from_rope(dna);
+        assert_eq!(dna_executor.defragment_policy(), DefragmentPolicy::Never);
+        dna_executor.execute_loops(1);
+        assert_eq!(dna_executor.progress().rope_count, 9);
+
+        dna_executor.set_defragment_policy(DefragmentPolicy::EveryIterations(3));
+        dna_executor.execute_loops(1);
+        assert_eq!(dna_executor.progress().rope_count, 8);
+        dna_executor.execute_loops(1);
+        assert_eq!(dna_executor.progress().rope_count, 1);
+        let stats = dna_executor.defragment_stats();
+        assert_eq!(
+            (stats.runs, stats.chunks_before, stats.chunks_after),
+            (1, 7, 1)
+        );
+        assert_eq!(stats.bases_copied, 16 * 7);
+    }
+
+    #[test]
+    fn case_step() {
+        let mut dna_executor = DnaExecutor::try_from("IIPIPICPIICICIIFICCIFPPIICCFPC").unwrap();
+        let step = dna_executor.step().unwrap();
+        assert_eq!(
+            step.pattern,
+            vec![
+                Pattern::GroupOpen,
+                Pattern::Skip(2),
+                Pattern::GroupClose,
+                Pattern::Base(P)
+            ]
+        );
+        assert_eq!(
+            step.template,
+            vec![
+                Template::Base(P),
+                Template::Base(I),
+                Template::NumberLevel(0, 0)
+            ]
+        );
+        assert_eq!(step.prefix_len, 26);
+        assert!(step.matched());
+        assert_eq!(step.position, Some(3));
+        assert_eq!(step.env, vec![0..2]);
+        assert!(step.rna.is_empty());
+        assert_eq!(dna_executor.dna_string(), "PICFC");
+
+        let mut dna_executor = DnaExecutor::try_from("IIIPIIIIIPIICIIC").unwrap();
+        let step = dna_executor.step().unwrap();
+        assert_eq!(step.rna, vec![Rna::Move]);
+        assert!(step.pattern.is_empty());
+        let stopped = dna_executor.step().unwrap_err();
+        assert_eq!(stopped.status, ExecutionStatus::Finished);
+        assert!(stopped.rna.is_empty());
+        assert!(dna_executor.is_finished());
+
+        // The RNA of a pattern running out of DNA is reported on stopping.
+        let mut dna_executor = DnaExecutor::try_from("IIIPIIIIIPIIC").unwrap();
+        let stopped = dna_executor.step().unwrap_err();
+        assert_eq!(stopped.status, ExecutionStatus::Finished);
+        assert_eq!(stopped.rna, vec![Rna::Move]);
+        assert_eq!(dna_executor.rna(), [Rna::Move]);
+    }
+
+    #[test]
+    fn case_state_accessors() {
+        let mut dna_executor = DnaExecutor::try_from("IIPIPICPIICICIIFICCIFPPIICCFPC").unwrap();
+        assert_eq!(dna_executor.dna_len(), 30);
+        assert_eq!(dna_executor.loops_count(), 0);
+        assert!(!dna_executor.is_finished());
+
+        dna_executor.execute_loops(1);
+        assert_eq!(dna_executor.dna_string(), "PICFC");
+        assert_eq!(dna_executor.dna().map(DnaRope::len), Some(5));
+        assert_eq!(dna_executor.loops_count(), 1);
+
+        dna_executor.execute();
+        assert!(dna_executor.is_finished());
+        assert_eq!(dna_executor.dna_len(), 0);
+        assert_eq!(dna_executor.dna_string(), "");
+    }
 }
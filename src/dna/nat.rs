@@ -0,0 +1,170 @@
+//! Natural numbers decoded by `nat`, which the spec leaves unbounded.
+
+use super::{
+    Dna::{self, *},
+    matchreplace::asnat,
+};
+
+use std::fmt;
+
+/// Natural number greater than `usize::MAX`, stored as 64-bit words with the
+/// least significant word first and no trailing zero words.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigNat {
+    words: Vec<u64>,
+}
+
+/// A number decoded from DNA, see [`BigNat`] for the ones past `usize::MAX`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Nat {
+    Small(usize),
+    Big(BigNat),
+}
+
+impl Nat {
+    /// Builds a number from 64-bit words, the least significant first.
+    pub fn from_words(mut words: Vec<u64>) -> Self {
+        while words.last() == Some(&0) {
+            words.pop();
+        }
+        match words[..] {
+            [] => Nat::Small(0),
+            [word] if usize::try_from(word).is_ok() => Nat::Small(word as usize),
+            _ => Nat::Big(BigNat { words }),
+        }
+    }
+
+    pub fn to_usize(&self) -> Option<usize> {
+        match self {
+            Nat::Small(n) => Some(*n),
+            Nat::Big(_) => None,
+        }
+    }
+
+    /// Encodes the number as DNA, the inverse of `nat`.
+    pub fn to_dna(&self) -> Vec<Dna> {
+        match self {
+            Nat::Small(n) => asnat(*n),
+            Nat::Big(n) => n.to_dna(),
+        }
+    }
+}
+
+impl BigNat {
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Encodes the number as DNA, `C` for every set bit and `I` for every
+    /// clear one starting from the least significant, terminated by `P`.
+    pub fn to_dna(&self) -> Vec<Dna> {
+        let last = self.words[self.words.len() - 1];
+        let bits = 64 * self.words.len() - last.leading_zeros() as usize;
+        let mut dna: Vec<_> = (0..bits)
+            .map(|x| {
+                if self.words[x / 64] >> (x % 64) & 1 == 1 {
+                    C
+                } else {
+                    I
+                }
+            })
+            .collect();
+        dna.push(P);
+        dna
+    }
+}
+
+/// Multiplies the number in `words` by ten and adds `digit`.
+pub(crate) fn push_digit(words: &mut Vec<u64>, digit: u32) {
+    let mut carry = digit as u128;
+    for word in words.iter_mut() {
+        let x = *word as u128 * 10 + carry;
+        *word = x as u64;
+        carry = x >> 64;
+    }
+    if carry != 0 {
+        words.push(carry as u64);
+    }
+}
+
+impl fmt::Display for Nat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Nat::Small(n) => write!(f, "{}", n),
+            Nat::Big(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl fmt::Display for BigNat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Decimal digits are produced 19 at a time, the most that fit a word.
+        const CHUNK: u128 = 10_000_000_000_000_000_000;
+        let mut words = self.words.clone();
+        let mut chunks = vec![];
+        while !words.is_empty() {
+            let mut rem = 0;
+            for word in words.iter_mut().rev() {
+                let x = rem << 64 | *word as u128;
+                *word = (x / CHUNK) as u64;
+                rem = x % CHUNK;
+            }
+            chunks.push(rem as u64);
+            while words.last() == Some(&0) {
+                words.pop();
+            }
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:019}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::read_dna;
+    use super::*;
+
+    fn decimal(text: &str) -> Nat {
+        let mut words = vec![];
+        for digit in text.chars() {
+            push_digit(&mut words, digit.to_digit(10).unwrap());
+        }
+        Nat::from_words(words)
+    }
+
+    #[test]
+    fn from_words() {
+        assert_eq!(Nat::from_words(vec![]), Nat::Small(0));
+        assert_eq!(Nat::from_words(vec![7, 0, 0]), Nat::Small(7));
+        let n = Nat::from_words(vec![0, 1, 0]);
+        assert_eq!(n.to_usize(), None);
+        assert_eq!(n, Nat::Big(BigNat { words: vec![0, 1] }));
+    }
+
+    #[test]
+    fn decimal_round_trip() {
+        for text in [
+            "0",
+            "1024",
+            "18446744073709551615",
+            "18446744073709551616",
+            "340282366920938463463374607431768211456",
+            "10000000000000000000000000000000000000000000000000000000000",
+        ] {
+            assert_eq!(decimal(text).to_string(), text);
+        }
+        assert_eq!(decimal("18446744073709551616"), Nat::from_words(vec![0, 1]));
+    }
+
+    #[test]
+    fn to_dna() {
+        assert_eq!(Nat::Small(11).to_dna(), read_dna("CCICP"));
+        let mut expected = vec![I; 64];
+        expected.extend([C, I, C, P]);
+        assert_eq!(Nat::from_words(vec![0, 5]).to_dna(), expected);
+    }
+}
@@ -0,0 +1,112 @@
+use super::{
+    Dna,
+    nat::{self, Nat},
+};
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum NotationError {
+    UnexpectedSymbol { symbol: char, offset: usize },
+    UnexpectedEnd,
+    InvalidNumber { offset: usize },
+    UnbalancedGroup { offset: usize },
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::UnexpectedSymbol { symbol, offset } => {
+                write!(f, "unexpected symbol {:?} at offset {}", symbol, offset)
+            }
+            NotationError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            NotationError::InvalidNumber { offset } => {
+                write!(f, "invalid number at offset {}", offset)
+            }
+            NotationError::UnbalancedGroup { offset } => {
+                write!(f, "unbalanced group at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for NotationError {}
+
+/// Character cursor shared by the pattern and template notation parsers,
+/// skipping whitespace between tokens.
+pub struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Cursor {
+            chars: text.char_indices().peekable(),
+            len: text.len(),
+        }
+    }
+
+    pub fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.len, |(offset, _)| *offset)
+    }
+
+    pub fn peek(&mut self) -> Option<char> {
+        while let Some((_, symbol)) = self.chars.peek() {
+            if !symbol.is_whitespace() {
+                return Some(*symbol);
+            }
+            self.chars.next();
+        }
+        None
+    }
+
+    pub fn next(&mut self) -> Option<(usize, char)> {
+        self.peek()?;
+        self.chars.next()
+    }
+
+    pub fn expect(&mut self, expected: char) -> Result<(), NotationError> {
+        match self.next() {
+            Some((_, symbol)) if symbol == expected => Ok(()),
+            Some((offset, symbol)) => Err(NotationError::UnexpectedSymbol { symbol, offset }),
+            None => Err(NotationError::UnexpectedEnd),
+        }
+    }
+
+    /// Reads a decimal number of any length.
+    pub fn nat(&mut self) -> Result<Nat, NotationError> {
+        self.peek();
+        let offset = self.offset();
+        let mut words = vec![];
+        let mut digits = 0;
+        while let Some(&(_, symbol)) = self.chars.peek() {
+            let Some(digit) = symbol.to_digit(10) else {
+                break;
+            };
+            nat::push_digit(&mut words, digit);
+            digits += 1;
+            self.chars.next();
+        }
+        if digits == 0 {
+            return Err(NotationError::InvalidNumber { offset });
+        }
+        Ok(Nat::from_words(words))
+    }
+
+    pub fn base(&mut self) -> Option<Dna> {
+        let base = Dna::from_char(self.peek()?).ok()?;
+        self.chars.next();
+        Some(base)
+    }
+
+    pub fn unexpected(&mut self) -> NotationError {
+        match self.next() {
+            Some((offset, symbol)) => NotationError::UnexpectedSymbol { symbol, offset },
+            None => NotationError::UnexpectedEnd,
+        }
+    }
+}
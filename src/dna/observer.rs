@@ -0,0 +1,50 @@
+use super::ExecutionStatus;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Snapshot of a [`super::DnaExecutor`] reported to an [`Observer`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Progress {
+    pub iterations: usize,
+    pub dna_len: usize,
+    pub rna_count: usize,
+    /// Number of chunks the DNA is stored in, see [`super::DnaRope::rope_count`].
+    pub rope_count: usize,
+}
+
+/// Receives progress of a run, see [`super::DnaExecutor::observe`].
+pub trait Observer {
+    /// Called after every iteration whose number is a multiple of the
+    /// interval given to [`super::DnaExecutor::observe`].
+    fn progress(&mut self, progress: &Progress);
+
+    /// Called when a run stops for any reason other than having executed
+    /// the requested number of iterations.
+    fn stopped(&mut self, _status: ExecutionStatus, _progress: &Progress) {}
+}
+
+/// Flag stopping a [`super::DnaExecutor`] from another thread. Clones share
+/// the flag, which stays set until [`Self::reset`].
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
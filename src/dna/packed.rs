@@ -0,0 +1,120 @@
+//! DNA packed 32 bases per word, the first base in the lowest bits.
+
+use super::Dna::{self, *};
+
+use std::ops::Range;
+
+pub const WORD_BASES: usize = 32;
+
+/// The lower bit of every base in a word.
+const LOW_BITS: u64 = 0x5555_5555_5555_5555;
+
+static BASES: [Dna; 4] = [I, C, F, P];
+
+/// Mask selecting the first `n <= WORD_BASES` bases of a word.
+pub fn mask(n: usize) -> u64 {
+    if n >= WORD_BASES {
+        !0
+    } else {
+        (1 << (2 * n)) - 1
+    }
+}
+
+/// Packs a run of bases, returning the words and the number of bases.
+pub fn pack<T: IntoIterator<Item = Dna>>(dna: T) -> (Vec<u64>, usize) {
+    let dna = dna.into_iter();
+    let mut words = Vec::with_capacity(dna.size_hint().0.div_ceil(WORD_BASES));
+    let mut len: usize = 0;
+    for base in dna {
+        if len.is_multiple_of(WORD_BASES) {
+            words.push(0);
+        }
+        *words.last_mut().unwrap() |= (base as u64) << (2 * (len % WORD_BASES));
+        len += 1;
+    }
+    (words, len)
+}
+
+/// Packs at most [`WORD_BASES`] bases into a single word.
+pub fn pack_word(dna: &[Dna]) -> u64 {
+    debug_assert!(dna.len() <= WORD_BASES);
+    dna.iter()
+        .rev()
+        .fold(0, |word, base| word << 2 | *base as u64)
+}
+
+pub fn base(words: &[u64], index: usize) -> &'static Dna {
+    let code = words[index / WORD_BASES] >> (2 * (index % WORD_BASES));
+    &BASES[(code & 3) as usize]
+}
+
+pub fn base_ref(base: Dna) -> &'static Dna {
+    &BASES[base as usize]
+}
+
+/// Counts every base in the given range, indexed by `Dna as usize`.
+pub fn count(words: &[u64], range: Range<usize>) -> [usize; 4] {
+    let mut counts = [0; 4];
+    let mut index = range.start;
+    while index < range.end {
+        let n = (range.end - index).min(WORD_BASES - index % WORD_BASES);
+        let word = (words[index / WORD_BASES] >> (2 * (index % WORD_BASES))) & mask(n);
+        let (low, high) = (word & LOW_BITS, word >> 1 & LOW_BITS);
+        counts[C as usize] += (low & !high).count_ones() as usize;
+        counts[F as usize] += (high & !low).count_ones() as usize;
+        counts[P as usize] += (low & high).count_ones() as usize;
+        index += n;
+    }
+    counts[I as usize] = range.len() - counts[1..].iter().sum::<usize>();
+    counts
+}
+
+/// Returns a word with the lower bit of every base equal to `base` set.
+pub fn find_base(word: u64, base: Dna) -> u64 {
+    let diff = word ^ (base as u64 * LOW_BITS);
+    !(diff | diff >> 1) & LOW_BITS
+}
+
+/// Returns [`WORD_BASES`] bases starting at `index`, bases past the end of
+/// `words` are zero.
+pub fn word_at(words: &[u64], index: usize) -> u64 {
+    let shift = 2 * (index % WORD_BASES);
+    let word = index / WORD_BASES;
+    let low = words[word] >> shift;
+    if shift == 0 {
+        low
+    } else {
+        low | words.get(word + 1).map_or(0, |x| x << (64 - shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_and_read() {
+        let dna: Vec<_> = (0..70).map(|x| BASES[x * 7 % 4]).collect();
+        let (words, len) = pack(dna.iter().cloned());
+        assert_eq!((words.len(), len), (3, 70));
+        for (index, expected) in dna.iter().enumerate() {
+            assert_eq!(base(&words, index), expected);
+        }
+        for index in 0..dna.len() {
+            let end = dna.len().min(index + WORD_BASES);
+            let expected = pack_word(&dna[index..end]);
+            assert_eq!(word_at(&words, index) & mask(end - index), expected);
+        }
+        for start in 0..dna.len() {
+            let mut expected = [0; 4];
+            for (end, base) in dna.iter().enumerate().skip(start) {
+                assert_eq!(count(&words, start..end), expected);
+                expected[*base as usize] += 1;
+            }
+        }
+        assert_eq!(find_base(pack_word(&[C, P, I, P]), P), 0b01_00_01_00);
+        assert_eq!(find_base(0, I), LOW_BITS);
+        assert_eq!(pack_word(&[C, P]), 0b11_01);
+        assert_eq!(mask(1), 0b11);
+    }
+}
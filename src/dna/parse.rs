@@ -0,0 +1,167 @@
+use super::Dna;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum ParseMode {
+    /// Every character must be one of `I`, `C`, `F` or `P`.
+    #[default]
+    Strict,
+    /// Whitespace is skipped and `#` starts a comment running to the end of the line.
+    Lenient,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseError {
+    UnknownSymbol {
+        symbol: char,
+        offset: usize,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownSymbol {
+                symbol,
+                offset,
+                line,
+                column,
+            } => write!(
+                f,
+                "unknown symbol {:?} at {}:{} (offset {})",
+                symbol, line, column, offset
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Incremental DNA parser which keeps track of the position in the source,
+/// so text may be fed in arbitrary pieces.
+pub struct DnaParser {
+    mode: ParseMode,
+    offset: usize,
+    line: usize,
+    column: usize,
+    in_comment: bool,
+}
+
+impl DnaParser {
+    pub fn new(mode: ParseMode) -> Self {
+        DnaParser {
+            mode,
+            offset: 0,
+            line: 1,
+            column: 1,
+            in_comment: false,
+        }
+    }
+
+    pub fn feed(&mut self, text: &str, dna: &mut Vec<Dna>) -> Result<(), ParseError> {
+        for symbol in text.chars() {
+            self.feed_char(symbol, dna)?;
+        }
+        Ok(())
+    }
+
+    fn feed_char(&mut self, symbol: char, dna: &mut Vec<Dna>) -> Result<(), ParseError> {
+        match Dna::from_char(symbol) {
+            Ok(base) if !self.in_comment => dna.push(base),
+            _ if self.mode == ParseMode::Lenient => {
+                if symbol == '\n' {
+                    self.in_comment = false;
+                } else if symbol == '#' {
+                    self.in_comment = true;
+                } else if !self.in_comment && !symbol.is_whitespace() {
+                    return Err(self.unknown_symbol(symbol));
+                }
+            }
+            _ => return Err(self.unknown_symbol(symbol)),
+        }
+        self.offset += symbol.len_utf8();
+        if symbol == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Ok(())
+    }
+
+    fn unknown_symbol(&self, symbol: char) -> ParseError {
+        ParseError::UnknownSymbol {
+            symbol,
+            offset: self.offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+pub fn parse_dna(dna_str: &str, mode: ParseMode) -> Result<Vec<Dna>, ParseError> {
+    let mut dna = Vec::with_capacity(dna_str.len());
+    DnaParser::new(mode).feed(dna_str, &mut dna)?;
+    Ok(dna)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Dna::*;
+    use super::*;
+
+    #[test]
+    fn strict() {
+        assert_eq!(Dna::from_char('F'), Ok(F));
+        assert_eq!(Dna::from_char('x'), Err('x'));
+        assert_eq!(parse_dna("ICFP", ParseMode::Strict), Ok(vec![I, C, F, P]));
+        assert_eq!(
+            parse_dna("IC\nFP", ParseMode::Strict),
+            Err(ParseError::UnknownSymbol {
+                symbol: '\n',
+                offset: 2,
+                line: 1,
+                column: 3
+            })
+        );
+    }
+
+    #[test]
+    fn lenient() {
+        assert_eq!(
+            parse_dna("IC # comment\n  FP\n", ParseMode::Lenient),
+            Ok(vec![I, C, F, P])
+        );
+        assert_eq!(
+            parse_dna("IC\n  Fé P", ParseMode::Lenient),
+            Err(ParseError::UnknownSymbol {
+                symbol: 'é',
+                offset: 6,
+                line: 2,
+                column: 4
+            })
+        );
+    }
+
+    #[test]
+    fn split_feed() {
+        let mut parser = DnaParser::new(ParseMode::Lenient);
+        let mut dna = vec![];
+        parser.feed("I # comm", &mut dna).unwrap();
+        parser.feed("ent C\nF", &mut dna).unwrap();
+        assert_eq!(dna, vec![I, F]);
+        assert_eq!(
+            parser.feed("x", &mut dna),
+            Err(ParseError::UnknownSymbol {
+                symbol: 'x',
+                offset: 15,
+                line: 2,
+                column: 2
+            })
+        );
+    }
+}
@@ -1,19 +1,36 @@
 use super::{
     Dna::{self, *},
-    DnaExecutor, DnaRopeIter, nat,
+    DnaRopeIter, RnaSink,
+    matchreplace::{asnat, quote},
+    nat,
+    nat::{BigNat, Nat},
+    notation::{Cursor, NotationError},
 };
 use crate::rna::Rna;
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Pattern {
     Base(Dna),
     Skip(usize),
+    /// Skip by a number past `usize::MAX`, which never matches since no DNA
+    /// is that long.
+    SkipOverflow(BigNat),
     Search(Vec<Dna>),
     GroupOpen,
     GroupClose,
 }
 
+impl Pattern {
+    /// Creates a [`Pattern::Skip`] or, if `n` does not fit, a [`Pattern::SkipOverflow`].
+    pub fn skip(n: Nat) -> Self {
+        match n {
+            Nat::Small(n) => Pattern::Skip(n),
+            Nat::Big(n) => Pattern::SkipOverflow(n),
+        }
+    }
+}
+
 impl fmt::Display for Pattern {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -22,6 +39,7 @@ impl fmt::Display for Pattern {
             match self {
                 Pattern::Base(dna) => dna.to_string(),
                 Pattern::Skip(n) => format!("![{}]", n),
+                Pattern::SkipOverflow(n) => format!("![{}]", n),
                 Pattern::Search(dna) => format!(
                     "?[{}]",
                     dna.iter().map(|x| x.to_string()).collect::<String>()
@@ -33,7 +51,7 @@ impl fmt::Display for Pattern {
     }
 }
 
-pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec<Pattern>> {
+pub(super) fn execute<R: RnaSink>(rna: &mut R, iter: &mut DnaRopeIter) -> Option<Vec<Pattern>> {
     let mut pattern = vec![];
     let mut lvl = 0;
     loop {
@@ -53,7 +71,7 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
                 }
                 Some(P) => {
                     if let Some(n) = nat(iter) {
-                        pattern.push(Pattern::Skip(n));
+                        pattern.push(Pattern::skip(n));
                     } else {
                         return None;
                     }
@@ -77,7 +95,8 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
                         }
                     }
                     Some(I) => {
-                        executor.add_rna(Rna::from_dna_iter(iter));
+                        let offset = iter.pos() - 3;
+                        rna.emit(offset, Rna::from_dna_iter(iter));
                     }
                     _ => return None,
                 },
@@ -88,6 +107,81 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
     }
 }
 
+/// Parses the textual notation produced by `Display`, e.g. `(?[IFPP])F`.
+pub fn parse(text: &str) -> Result<Vec<Pattern>, NotationError> {
+    let mut cursor = Cursor::new(text);
+    let mut pattern = vec![];
+    let mut open = vec![];
+    while let Some(symbol) = cursor.peek() {
+        if let Some(base) = cursor.base() {
+            pattern.push(Pattern::Base(base));
+            continue;
+        }
+        match symbol {
+            '!' => {
+                cursor.next();
+                cursor.expect('[')?;
+                pattern.push(Pattern::skip(cursor.nat()?));
+                cursor.expect(']')?;
+            }
+            '?' => {
+                cursor.next();
+                cursor.expect('[')?;
+                let mut c = vec![];
+                while let Some(base) = cursor.base() {
+                    c.push(base);
+                }
+                cursor.expect(']')?;
+                pattern.push(Pattern::Search(c));
+            }
+            '(' => {
+                open.push(cursor.offset());
+                cursor.next();
+                pattern.push(Pattern::GroupOpen);
+            }
+            ')' => {
+                let offset = cursor.offset();
+                if open.pop().is_none() {
+                    return Err(NotationError::UnbalancedGroup { offset });
+                }
+                cursor.next();
+                pattern.push(Pattern::GroupClose);
+            }
+            _ => return Err(cursor.unexpected()),
+        }
+    }
+    if let Some(offset) = open.pop() {
+        return Err(NotationError::UnbalancedGroup { offset });
+    }
+    Ok(pattern)
+}
+
+/// Encodes `pattern` as DNA including the terminating `IIC`, the inverse of [`execute`].
+pub fn encode(pattern: &[Pattern]) -> Vec<Dna> {
+    let mut dna = vec![];
+    for p in pattern {
+        match p {
+            Pattern::Base(base) => dna.extend(quote([base])),
+            Pattern::Skip(n) => {
+                dna.extend([I, P]);
+                dna.extend(asnat(*n));
+            }
+            Pattern::SkipOverflow(n) => {
+                dna.extend([I, P]);
+                dna.extend(n.to_dna());
+            }
+            Pattern::Search(c) => {
+                dna.extend([I, F, F]);
+                dna.extend(quote(c));
+            }
+            Pattern::GroupOpen => dna.extend([I, I, P]),
+            Pattern::GroupClose => dna.extend([I, I, C]),
+        }
+    }
+    dna.extend([I, I, C]);
+    dna
+}
+
 fn consts(iter: &mut DnaRopeIter) -> Vec<Dna> {
     let mut c = vec![];
     loop {
@@ -118,20 +212,20 @@ fn consts(iter: &mut DnaRopeIter) -> Vec<Dna> {
 
 #[cfg(test)]
 mod tests {
+    use super::super::{DnaRope, debug, read_dna};
     use super::*;
 
-    fn pattern_for_test<T: Sized>(dna: &str, f: fn(&mut DnaExecutor, &mut DnaRopeIter) -> T) -> T {
-        let mut dna_executor = DnaExecutor::from(dna);
-        let dna = dna_executor.dna.take().unwrap();
+    fn pattern_for_test<T: Sized>(dna: &str, f: fn(&mut Vec<Rna>, &mut DnaRopeIter) -> T) -> T {
+        let dna = DnaRope::from(read_dna(dna));
         let mut iter = dna.iter();
-        f(&mut dna_executor, &mut iter)
+        f(&mut vec![], &mut iter)
     }
 
     fn pattern_run(dna: &str) -> Option<Vec<Pattern>> {
         pattern_for_test(dna, execute)
     }
 
-    fn pattern_nat(dna: &str) -> Option<usize> {
+    fn pattern_nat(dna: &str) -> Option<Nat> {
         pattern_for_test(dna, |_, iter| nat(iter))
     }
 
@@ -141,11 +235,24 @@ mod tests {
 
     #[test]
     fn case_pattern_nat() {
-        assert_eq!(pattern_nat("CCICP"), Some(11));
-        assert_eq!(pattern_nat("CFFFFFFFIIIIIIIIIIIP"), Some(1));
+        assert_eq!(pattern_nat("CCICP"), Some(Nat::Small(11)));
+        assert_eq!(pattern_nat("CFFFFFFFIIIIIIIIIIIP"), Some(Nat::Small(1)));
         assert_eq!(
             pattern_nat("IIIIICIICCIIIIIIIIIIIIIIP"),
-            Some(32 + 256 + 512)
+            Some(Nat::Small(32 + 256 + 512))
+        );
+        assert_eq!(pattern_nat(&"C".repeat(64)), None);
+        assert_eq!(
+            pattern_nat(&format!("{}P", "C".repeat(64))),
+            Some(Nat::Small(usize::MAX))
+        );
+        assert_eq!(
+            pattern_nat(&format!("{}CIIP", "I".repeat(64))),
+            Some(Nat::from_words(vec![0, 1]))
+        );
+        assert_eq!(
+            pattern_nat(&format!("C{}P", "I".repeat(200))),
+            Some(Nat::Small(1))
         );
     }
 
@@ -169,6 +276,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn case_pattern_parse() {
+        assert_eq!(
+            parse("(?[IFPP])F"),
+            Ok(vec![
+                Pattern::GroupOpen,
+                Pattern::Search(vec![I, F, P, P]),
+                Pattern::GroupClose,
+                Pattern::Base(F)
+            ])
+        );
+        assert_eq!(
+            parse(" ( ![12] ) P ?[] "),
+            Ok(vec![
+                Pattern::GroupOpen,
+                Pattern::Skip(12),
+                Pattern::GroupClose,
+                Pattern::Base(P),
+                Pattern::Search(vec![])
+            ])
+        );
+        assert_eq!(
+            parse("(I"),
+            Err(NotationError::UnbalancedGroup { offset: 0 })
+        );
+        assert_eq!(
+            parse("I)"),
+            Err(NotationError::UnbalancedGroup { offset: 1 })
+        );
+        assert_eq!(
+            parse("![x]"),
+            Err(NotationError::InvalidNumber { offset: 2 })
+        );
+        assert_eq!(
+            parse("IX"),
+            Err(NotationError::UnexpectedSymbol {
+                symbol: 'X',
+                offset: 1
+            })
+        );
+        assert_eq!(parse("?[IC"), Err(NotationError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn case_pattern_encode() {
+        let pattern = parse("(?[IFPP])F").unwrap();
+        assert_eq!(encode(&pattern), read_dna("IIPIFFCPICICIICPIIC"));
+
+        let text = "(![0])((?[ICFP]![1024]))ICFP?[]";
+        let pattern = parse(text).unwrap();
+        let dna = DnaRope::from(encode(&pattern));
+        let mut iter = dna.iter();
+        let decoded = execute(&mut vec![], &mut iter).unwrap();
+        assert_eq!(iter.pos(), dna.len());
+        assert_eq!(debug(&decoded), text);
+    }
+
+    #[test]
+    fn case_pattern_overflow() {
+        let text = "![18446744073709551616]![18446744073709551615]";
+        let pattern = parse(text).unwrap();
+        assert_eq!(
+            pattern,
+            vec![
+                Pattern::skip(Nat::from_words(vec![0, 1])),
+                Pattern::Skip(usize::MAX)
+            ]
+        );
+        assert!(matches!(pattern[0], Pattern::SkipOverflow(_)));
+        let dna = DnaRope::from(encode(&pattern));
+        let mut iter = dna.iter();
+        let decoded = execute(&mut vec![], &mut iter).unwrap();
+        assert_eq!(iter.pos(), dna.len());
+        assert_eq!(decoded, pattern);
+        assert_eq!(debug(&decoded), text);
+    }
+
     #[test]
     fn case_pattern_selfcheck_start_prefix() {
         // (?[IFPP])F
@@ -1,311 +1,888 @@
-use super::Dna;
-
-use std::cmp::Ordering;
+//! DNA rope stored as a persistent AVL tree of chunks.
+//!
+//! Nodes are shared through `Rc`, so cloning a rope is O(1) and split,
+//! concatenation and seeking are O(log n) in the number of chunks. Chunks
+//! are slices of reference counted storage packed 32 bases per word, so
+//! splitting never copies DNA, while a slice keeps its whole storage alive
+//! until [`DnaRope::defragment`]. Protected DNA can be stored as a source
+//! rope with a quote level, see [`DnaRope::quoted`].
+
+use super::{
+    Dna::{self, *},
+    packed::{self, WORD_BASES},
+};
+
+use std::fmt;
 use std::ops::Range;
+use std::rc::Rc;
+
+/// Chunks meeting at a concatenation are merged if they fit in this length.
+const MERGE_LEN: usize = 256;
+
+const QUOTED_OVERFLOW: &str = "quoted DNA length overflows usize";
 
 #[derive(Clone)]
-pub struct DnaRope {
-    dna: Vec<Vec<Dna>>,
-    index: Vec<usize>,
+struct Chunk {
+    data: Rc<[u64]>,
+    start: usize,
+    end: usize,
 }
 
-impl From<Vec<Dna>> for DnaRope {
-    fn from(value: Vec<Dna>) -> Self {
-        Self::from_raw(vec![value])
+impl Chunk {
+    fn new<T: IntoIterator<Item = Dna>>(dna: T) -> Self {
+        let (words, len) = packed::pack(dna);
+        Chunk {
+            data: words.into(),
+            start: 0,
+            end: len,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Dna> + '_ {
+        (self.start..self.end).map(|x| *packed::base(&self.data, x))
+    }
+
+    fn slice(&self, range: Range<usize>) -> Self {
+        Chunk {
+            data: self.data.clone(),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
     }
 }
 
-impl Default for DnaRope {
-    fn default() -> Self {
-        Self::new()
+/// Lazily protected DNA, see [`DnaRope::quoted`]. The source only holds
+/// chunks, so every decoded offset maps to a base of the source.
+struct Quoted {
+    source: DnaRope,
+    level: usize,
+    len: usize,
+    counts: [usize; 4],
+}
+
+impl Quoted {
+    /// Returns `None` if the quoted DNA would be longer than `usize::MAX` bases.
+    fn new(source: DnaRope, level: usize) -> Option<Self> {
+        let mut counts = [0; 4];
+        if let Some(root) = &source.root {
+            root.count(&mut counts);
+        }
+        for _ in 0..level {
+            let [i, c, f, p] = counts;
+            counts = [p, i.checked_add(p)?, c, f];
+        }
+        let len = counts
+            .iter()
+            .try_fold(0usize, |len, x| len.checked_add(*x))?;
+        Some(Quoted {
+            source,
+            level,
+            len,
+            counts,
+        })
+    }
+
+    /// Returns the index of the source base whose expansion contains the
+    /// decoded offset `at`, the base and the offset in its expansion.
+    fn locate(&self, mut at: usize, lens: &[usize; 4]) -> (usize, Dna, usize) {
+        let mut index = 0;
+        let root = self.source.root.as_ref().expect("quoted DNA is empty");
+        let base = locate_quoted(root, lens, &mut at, &mut index).expect("quoted DNA is too short");
+        (index, base, at)
     }
 }
 
-impl DnaRope {
-    pub fn new() -> Self {
-        Self {
-            dna: Vec::new(),
-            index: Vec::new(),
+/// Bases with the number of times they are quoted.
+type QuotedBases = Vec<(Dna, usize)>;
+
+/// Lengths of every base quoted from 0 to `level` times, saturating.
+fn quoted_lens(level: usize) -> Vec<[usize; 4]> {
+    let mut lens = vec![[1usize; 4]];
+    for _ in 0..level {
+        let [i, c, f, p] = lens[lens.len() - 1];
+        lens.push([c, f, p, i.saturating_add(c)]);
+    }
+    lens
+}
+
+/// Walks the chunks of a quoted source up to the base whose expansion
+/// contains `at`, counting the bases before it in `index` and subtracting
+/// their expanded lengths from `at`, a word at a time where possible.
+fn locate_quoted(node: &Node, lens: &[usize; 4], at: &mut usize, index: &mut usize) -> Option<Dna> {
+    let chunk = match node {
+        Node::Leaf(chunk) => chunk,
+        Node::Branch { left, right, .. } => {
+            return locate_quoted(left, lens, at, index)
+                .or_else(|| locate_quoted(right, lens, at, index));
         }
+        Node::Quoted(_) => unreachable!("quoted sources only hold chunks"),
+    };
+    let mut start = chunk.start;
+    while start < chunk.end {
+        let end = chunk.end.min((start / WORD_BASES + 1) * WORD_BASES);
+        let counts = packed::count(&chunk.data, start..end);
+        let len: usize = counts.iter().zip(lens).map(|(n, len)| n * len).sum();
+        if len <= *at {
+            *at -= len;
+            *index += end - start;
+            start = end;
+            continue;
+        }
+        for base in (start..end).map(|x| *packed::base(&chunk.data, x)) {
+            if *at < lens[base as usize] {
+                return Some(base);
+            }
+            *at -= lens[base as usize];
+            *index += 1;
+        }
+        unreachable!("the word expands past the offset");
     }
+    None
+}
 
-    pub fn from_raw(dna: Vec<Vec<Dna>>) -> Self {
-        let index = Self::create_index(&dna);
-        Self { dna, index }
+/// Splits `base` quoted `level` times at `0 < offset < len`. Returns the
+/// parts before the offset in order and the parts after it as a stack, the
+/// next one last like [`QuoteDecoder::pending`].
+fn split_base(
+    mut base: Dna,
+    mut level: usize,
+    mut offset: usize,
+    lens: &[[usize; 4]],
+) -> (QuotedBases, QuotedBases) {
+    let mut before = vec![];
+    let mut after = vec![];
+    while offset > 0 {
+        level -= 1;
+        if base != P {
+            base = [C, F, P][base as usize];
+            continue;
+        }
+        let len = lens[level][I as usize];
+        if offset < len {
+            after.push((C, level));
+            base = I;
+        } else {
+            before.push((I, level));
+            offset -= len;
+            base = C;
+        }
+    }
+    after.push((base, level));
+    (before, after)
+}
+
+/// `source` quoted `level` times, decoded at once if it is short.
+fn quoted_part(source: DnaRope, level: usize) -> Option<Rc<Node>> {
+    let quoted = DnaRope::quoted(source, level);
+    if quoted.len() <= MERGE_LEN && matches!(quoted.root.as_deref(), Some(Node::Quoted(_))) {
+        return Some(Rc::new(Node::Leaf(Chunk::new(quoted.iter().cloned()))));
     }
+    quoted.root
+}
 
-    fn create_index(dna: &Vec<Vec<Dna>>) -> Vec<usize> {
-        let mut index = Vec::with_capacity(dna.len());
-        let mut count = 0;
-        for subdna in dna {
-            count += subdna.len();
-            index.push(count);
+/// Splits a quoted node at `0 < at < len` without decoding it: the parts
+/// before and after the source base containing `at` stay quoted, that base
+/// is split into bases quoted fewer times.
+fn split_quoted(quoted: &Quoted, at: usize) -> (Option<Rc<Node>>, Option<Rc<Node>>) {
+    let lens = quoted_lens(quoted.level);
+    let (index, base, offset) = quoted.locate(at, &lens[quoted.level]);
+    let (before, after) = split_base(base, quoted.level, offset, &lens);
+    let source = &quoted.source;
+    let mut prefix = quoted_part(source.slice(0..index), quoted.level);
+    for (base, level) in before {
+        prefix = concat(prefix, quoted_part(DnaRope::from(vec![base]), level));
+    }
+    let mut suffix = quoted_part(source.slice(index + 1..source.len()), quoted.level);
+    for (base, level) in after {
+        suffix = concat(quoted_part(DnaRope::from(vec![base]), level), suffix);
+    }
+    (prefix, suffix)
+}
+
+/// Decodes bases of the source iterator quoted `level` times.
+#[derive(Clone)]
+struct QuoteDecoder<'a> {
+    source: Iter<'a>,
+    level: usize,
+    /// Bases with the number of times they are still to be quoted, the next
+    /// one last.
+    pending: QuotedBases,
+}
+
+impl<'a> QuoteDecoder<'a> {
+    fn new(source: Iter<'a>, level: usize) -> Self {
+        QuoteDecoder {
+            source,
+            level,
+            pending: vec![],
         }
-        index
     }
 
-    pub fn prepend(&mut self, mut prefix: DnaRope) {
-        prefix
-            .dna
-            .drain(..)
-            .rev()
-            .for_each(|subdna| self.dna.insert(0, subdna));
-        self.index = Self::create_index(&self.dna);
+    /// Decoder of `quoted` positioned at the decoded offset `at`.
+    fn at(quoted: &'a Quoted, at: usize) -> Self {
+        if at == 0 {
+            return Self::new(quoted.source.iter(), quoted.level);
+        }
+        let lens = quoted_lens(quoted.level);
+        let (index, base, offset) = quoted.locate(at, &lens[quoted.level]);
+        let (_, pending) = split_base(base, quoted.level, offset, &lens);
+        QuoteDecoder {
+            source: quoted.source.iter_at(index + 1),
+            level: quoted.level,
+            pending,
+        }
     }
 
-    pub fn append_dna(&mut self, subdna: Vec<Dna>) {
-        let count = self.len() + subdna.len();
-        self.dna.push(subdna);
-        self.index.push(count);
+    fn read_word(&mut self, n: usize) -> u64 {
+        (0..n).fold(0, |word, i| {
+            word | (self.next().expect("quoted DNA is too short") as u64) << (2 * i)
+        })
     }
+}
+
+impl Iterator for QuoteDecoder<'_> {
+    type Item = Dna;
 
-    pub fn append(&mut self, mut suffix: DnaRope) {
-        let mut count = self.len();
-        let from = self.dna.len();
-        self.dna.append(&mut suffix.dna);
-        self.index.reserve(self.dna.len() - from);
-        for dna in &self.dna[from..] {
-            count += dna.len();
-            self.index.push(count);
+    fn next(&mut self) -> Option<Dna> {
+        let (mut base, mut level) = match self.pending.pop() {
+            Some(pending) => pending,
+            None => (*self.source.next()?, self.level),
+        };
+        loop {
+            // I, C and F are quoted as the next base and P as IC.
+            let steps = P as usize - base as usize;
+            if level <= steps {
+                return Some([I, C, F, P][base as usize + level]);
+            }
+            level -= steps + 1;
+            self.pending.push((C, level));
+            base = I;
         }
     }
+}
 
-    pub fn get_range(&self, range: Range<usize>) -> Vec<&Dna> {
-        self.iter_from_range(range).collect()
+enum Node {
+    Leaf(Chunk),
+    Quoted(Quoted),
+    Branch {
+        left: Rc<Node>,
+        right: Rc<Node>,
+        len: usize,
+        height: usize,
+        leaves: usize,
+    },
+}
+
+impl Node {
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(chunk) => chunk.len(),
+            Node::Quoted(quoted) => quoted.len,
+            Node::Branch { len, .. } => *len,
+        }
     }
 
-    pub fn as_vec(&self) -> Vec<&Dna> {
-        self.iter().collect()
+    fn height(&self) -> usize {
+        match self {
+            Node::Leaf(_) | Node::Quoted(_) => 0,
+            Node::Branch { height, .. } => *height,
+        }
     }
 
-    pub fn len(&self) -> usize {
-        *self.index.last().unwrap_or(&0)
+    fn leaves(&self) -> usize {
+        match self {
+            Node::Leaf(_) | Node::Quoted(_) => 1,
+            Node::Branch { leaves, .. } => *leaves,
+        }
     }
 
-    pub fn rope_count(&self) -> usize {
-        self.dna.len()
+    fn children(&self) -> (&Rc<Node>, &Rc<Node>) {
+        match self {
+            Node::Branch { left, right, .. } => (left, right),
+            _ => unreachable!("leaf has no children"),
+        }
     }
 
-    pub fn defragment(self) -> DnaRope {
-        let dna: Vec<Dna> = self.iter().cloned().collect();
-        DnaRope::from(dna)
+    /// Adds the number of every base, indexed by `Dna as usize`.
+    fn count(&self, counts: &mut [usize; 4]) {
+        let leaf = match self {
+            Node::Leaf(chunk) => packed::count(&chunk.data, chunk.start..chunk.end),
+            Node::Quoted(quoted) => quoted.counts,
+            Node::Branch { left, right, .. } => {
+                left.count(counts);
+                right.count(counts);
+                return;
+            }
+        };
+        counts.iter_mut().zip(leaf).for_each(|(x, n)| *x += n);
     }
 
-    pub fn iter(&self) -> Iter<'_> {
-        Iter {
-            rope: self,
-            index: 0,
-            vec: 0,
-            absolute_index: 0,
+    /// Returns the leaf containing `pos` together with its start offset.
+    fn locate(&self, mut pos: usize) -> (&Node, usize) {
+        let mut node = self;
+        let mut start = 0;
+        loop {
+            match node {
+                Node::Branch { left, right, .. } => {
+                    if pos < left.len() {
+                        node = left;
+                    } else {
+                        pos -= left.len();
+                        start += left.len();
+                        node = right;
+                    }
+                }
+                leaf => return (leaf, start),
+            }
         }
     }
 
-    pub fn iter_seek(&self, iter: &mut Iter<'_>, n: usize) {
-        if let Some((vec, index)) = self.index_pair(n) {
-            iter.vec = vec;
-            iter.index = index;
-            iter.absolute_index = n;
+    fn first_leaf(&self) -> &Node {
+        match self {
+            Node::Branch { left, .. } => left.first_leaf(),
+            leaf => leaf,
+        }
+    }
+
+    fn last_leaf(&self) -> &Node {
+        match self {
+            Node::Branch { right, .. } => right.last_leaf(),
+            leaf => leaf,
+        }
+    }
+}
+
+fn branch(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+    Rc::new(Node::Branch {
+        len: left.len() + right.len(),
+        height: left.height().max(right.height()) + 1,
+        leaves: left.leaves() + right.leaves(),
+        left,
+        right,
+    })
+}
+
+/// Creates a branch, rotating once or twice if the heights differ by two.
+fn balance(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+    if left.height() > right.height() + 1 {
+        let (ll, lr) = left.children();
+        if ll.height() >= lr.height() {
+            branch(ll.clone(), branch(lr.clone(), right))
+        } else {
+            let (lrl, lrr) = lr.children();
+            branch(branch(ll.clone(), lrl.clone()), branch(lrr.clone(), right))
+        }
+    } else if right.height() > left.height() + 1 {
+        let (rl, rr) = right.children();
+        if rr.height() >= rl.height() {
+            branch(branch(left, rl.clone()), rr.clone())
         } else {
-            iter.vec = 0;
-            iter.index = 0;
-            iter.absolute_index = self.len();
+            let (rll, rlr) = rl.children();
+            branch(branch(left, rll.clone()), branch(rlr.clone(), rr.clone()))
         }
+    } else {
+        branch(left, right)
     }
+}
 
-    pub fn split_off(&mut self, at: usize) -> Self {
-        if at == 0 {
-            return Self {
-                dna: std::mem::take(&mut self.dna),
-                index: std::mem::take(&mut self.index),
+/// Concatenates two trees of arbitrary heights keeping the AVL invariant.
+fn join(left: Rc<Node>, right: Rc<Node>) -> Rc<Node> {
+    if left.height() > right.height() + 1 {
+        let (ll, lr) = left.children();
+        balance(ll.clone(), join(lr.clone(), right))
+    } else if right.height() > left.height() + 1 {
+        let (rl, rr) = right.children();
+        balance(join(left, rl.clone()), rr.clone())
+    } else {
+        branch(left, right)
+    }
+}
+
+fn join_opt(left: Option<Rc<Node>>, right: Option<Rc<Node>>) -> Option<Rc<Node>> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(join(left, right)),
+        (left, right) => left.or(right),
+    }
+}
+
+/// Like [`join_opt`], but merges small chunks meeting at the boundary.
+fn concat(left: Option<Rc<Node>>, right: Option<Rc<Node>>) -> Option<Rc<Node>> {
+    let (left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        (left, right) => return left.or(right),
+    };
+    let (Node::Leaf(last), Node::Leaf(first)) = (left.last_leaf(), right.first_leaf()) else {
+        return Some(join(left, right));
+    };
+    if last.len() + first.len() > MERGE_LEN {
+        return Some(join(left, right));
+    }
+    let chunk = Chunk::new(last.iter().chain(first.iter()));
+    let (left, _) = split(&left, left.len() - last.len());
+    let (_, right) = split(&right, first.len());
+    join_opt(join_opt(left, Some(Rc::new(Node::Leaf(chunk)))), right)
+}
+
+fn split(node: &Rc<Node>, at: usize) -> (Option<Rc<Node>>, Option<Rc<Node>>) {
+    if at == 0 {
+        return (None, Some(node.clone()));
+    }
+    if at >= node.len() {
+        return (Some(node.clone()), None);
+    }
+    let chunk = match &**node {
+        Node::Leaf(chunk) => chunk,
+        Node::Quoted(quoted) => return split_quoted(quoted, at),
+        Node::Branch { left, right, .. } => {
+            return if at <= left.len() {
+                let (a, b) = split(left, at);
+                (a, join_opt(b, Some(right.clone())))
+            } else {
+                let (a, b) = split(right, at - left.len());
+                (join_opt(Some(left.clone()), a), b)
             };
         }
+    };
+    (
+        Some(Rc::new(Node::Leaf(chunk.slice(0..at)))),
+        Some(Rc::new(Node::Leaf(chunk.slice(at..chunk.len())))),
+    )
+}
 
-        if let Some((vec, at)) = self.index_pair(at) {
-            let mut postfix = self.dna.split_off(vec);
-            self.index.truncate(vec);
+/// Builds a balanced tree from non-empty leaves.
+fn build(leaves: &[Rc<Node>]) -> Option<Rc<Node>> {
+    match leaves.len() {
+        0 => None,
+        1 => Some(leaves[0].clone()),
+        len => {
+            let (left, right) = leaves.split_at(len / 2);
+            join_opt(build(left), build(right))
+        }
+    }
+}
 
-            if at > 0 {
-                let vec_postfix = postfix[0].split_off(at);
-                let vec_suffix = std::mem::replace(&mut postfix[0], vec_postfix);
-                let vec_suffix_len = vec_suffix.len();
-                self.dna.push(vec_suffix);
+fn collect_leaves<'a>(node: &'a Rc<Node>, leaves: &mut Vec<&'a Rc<Node>>) {
+    match &**node {
+        Node::Branch { left, right, .. } => {
+            collect_leaves(left, leaves);
+            collect_leaves(right, leaves);
+        }
+        _ => leaves.push(node),
+    }
+}
 
-                let last_index = self.len() + vec_suffix_len;
-                self.index.push(last_index);
-            }
+/// Moves the leaves of `run` into `merged` as a single chunk, returning the
+/// number of bases copied.
+fn merge_run(run: &mut Vec<&Rc<Node>>, merged: &mut Vec<Rc<Node>>) -> usize {
+    let copied = match run[..] {
+        [] => 0,
+        [leaf] => {
+            merged.push(leaf.clone());
+            0
+        }
+        _ => {
+            let chunk = Chunk::new(run.iter().flat_map(|leaf| match &***leaf {
+                Node::Leaf(chunk) => chunk.iter(),
+                _ => unreachable!("only chunks are merged"),
+            }));
+            let len = chunk.len();
+            merged.push(Rc::new(Node::Leaf(chunk)));
+            len
+        }
+    };
+    run.clear();
+    copied
+}
 
-            Self::from_raw(postfix)
-        } else {
-            Self::from(vec![])
+#[derive(Clone, Default)]
+pub struct DnaRope {
+    root: Option<Rc<Node>>,
+}
+
+impl From<Vec<Dna>> for DnaRope {
+    fn from(value: Vec<Dna>) -> Self {
+        Self::from_raw(vec![value])
+    }
+}
+
+impl fmt::Display for DnaRope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.iter().try_for_each(|dna| write!(f, "{}", dna))
+    }
+}
+
+impl fmt::Debug for DnaRope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("DnaRope")
+            .field(&format_args!("{}", self))
+            .finish()
+    }
+}
+
+/// Ropes are equal when they hold the same bases, however they are chunked.
+impl PartialEq for DnaRope {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for DnaRope {}
+
+impl DnaRope {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Creates a rope with the given chunks, empty chunks are dropped.
+    pub fn from_raw(dna: Vec<Vec<Dna>>) -> Self {
+        let leaves: Vec<_> = dna
+            .into_iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| Rc::new(Node::Leaf(Chunk::new(x))))
+            .collect();
+        Self {
+            root: build(&leaves),
         }
     }
 
-    fn index_pair(&self, i: usize) -> Option<(usize, usize)> {
-        match self.index.binary_search(&i) {
-            Ok(vec) => {
-                if vec + 1 < self.dna.len() {
-                    Some((vec + 1, 0))
-                } else {
-                    None
+    pub fn prepend(&mut self, prefix: DnaRope) {
+        self.root = concat(prefix.root, self.root.take());
+    }
+
+    pub fn append_dna(&mut self, subdna: Vec<Dna>) {
+        self.append(DnaRope::from(subdna));
+    }
+
+    pub fn append(&mut self, suffix: DnaRope) {
+        self.root = concat(self.root.take(), suffix.root);
+    }
+
+    pub fn get_range(&self, range: Range<usize>) -> Vec<&Dna> {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "Wrong range: {:?}",
+            range
+        );
+        self.iter_at(range.start)
+            .take(range.end - range.start)
+            .collect()
+    }
+
+    pub fn as_vec(&self) -> Vec<&Dna> {
+        self.iter().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |x| x.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Number of chunks in the rope.
+    pub fn rope_count(&self) -> usize {
+        self.root.as_ref().map_or(0, |x| x.leaves())
+    }
+
+    /// Copies the rope into a single chunk, releasing storage of dropped parts.
+    pub fn defragment(self) -> DnaRope {
+        if self.is_empty() {
+            return self;
+        }
+        let chunk = Chunk::new(self.iter().cloned());
+        DnaRope {
+            root: Some(Rc::new(Node::Leaf(chunk))),
+        }
+    }
+
+    /// Copies runs of adjacent chunks shorter than `min_len` into chunks of
+    /// at least `min_len` bases where possible, sharing every other chunk.
+    /// Returns the rope along with the number of bases copied.
+    pub fn merge_small_chunks(self, min_len: usize) -> (DnaRope, usize) {
+        let Some(root) = &self.root else {
+            return (self, 0);
+        };
+        let mut leaves = vec![];
+        collect_leaves(root, &mut leaves);
+        let mut merged = vec![];
+        let mut run = vec![];
+        let mut run_len = 0;
+        let mut copied = 0;
+        for leaf in leaves {
+            if let Node::Leaf(chunk) = &**leaf
+                && chunk.len() < min_len
+            {
+                run.push(leaf);
+                run_len += chunk.len();
+                if run_len >= min_len {
+                    copied += merge_run(&mut run, &mut merged);
+                    run_len = 0;
                 }
+            } else {
+                copied += merge_run(&mut run, &mut merged);
+                run_len = 0;
+                merged.push(leaf.clone());
             }
-            Err(vec) => Some((vec, i + self.dna[vec].len() - self.index[vec])),
         }
+        copied += merge_run(&mut run, &mut merged);
+        (
+            DnaRope {
+                root: build(&merged),
+            },
+            copied,
+        )
     }
 
-    pub fn split_by_ranges(mut self, ranges: &[Range<usize>]) -> Vec<DnaRope> {
-        if ranges.len() == 1 {
-            let range = &ranges[0];
-            if range.end < self.len() {
-                self.split_off(range.end);
-            }
-            return vec![if range.start > 0 {
-                self.split_off(range.start)
-            } else {
-                self
-            }];
-        }
-        let mut ranges: Vec<(usize, &Range<usize>)> = ranges.iter().enumerate().collect();
-        ranges.sort_unstable_by(|a, b| match a.1.start.cmp(&b.1.start) {
-            Ordering::Equal => b.1.end.cmp(&a.1.end),
-            x => x,
-        });
-
-        let mut result: Vec<(_, Option<Vec<_>>, &Range<_>)> = Vec::with_capacity(ranges.len());
-        for range in ranges {
-            let have_intersection = result
-                .iter()
-                .any(|x| !((range.1.start >= x.2.end) || (range.1.end <= x.2.start)));
-            debug!(
-                "range[{}] {} {:?}({})",
-                range.0,
-                have_intersection,
-                range.1,
-                range.1.end - range.1.start
-            );
-            result.push((
-                range.0,
-                if have_intersection {
-                    Some(self.copy_from_range(range.1.clone()))
-                } else {
-                    None
-                },
-                range.1,
-            ));
-        }
-
-        let mut dna_ropes = Vec::with_capacity(result.len());
-        while let Some(mut range) = result.pop() {
-            dna_ropes.push((
-                range.0,
-                if let Some(dna) = range.1.take() {
-                    DnaRope::from(dna)
-                } else {
-                    if range.2.end < self.len() {
-                        self.split_off(range.2.end);
-                    }
-                    self.split_off(range.2.start)
-                },
-            ));
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_at(0)
+    }
+
+    fn iter_at(&self, pos: usize) -> Iter<'_> {
+        Iter {
+            rope: self,
+            words: &[],
+            offset: 0,
+            chunk_start: 0,
+            chunk_len: 0,
+            quoted: None,
+            pos,
         }
-        dna_ropes.sort_unstable_by_key(|x| x.0);
+    }
 
-        dna_ropes.into_iter().map(|x| x.1).collect()
+    pub fn iter_seek(&self, iter: &mut Iter<'_>, n: usize) {
+        iter.seek(n);
     }
 
-    fn copy_from_range(&self, range: Range<usize>) -> Vec<Dna> {
-        self.iter_from_range(range).cloned().collect()
+    /// Returns `source` protected `level` times like [`super::matchreplace::protect`],
+    /// decoding the bases only when they are read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the protected DNA overflows `usize`.
+    pub fn quoted(source: DnaRope, level: usize) -> DnaRope {
+        Self::checked_quoted(source, level).expect(QUOTED_OVERFLOW)
     }
 
-    fn iter_from_range(&self, range: Range<usize>) -> std::iter::Take<Iter<'_>> {
-        if let Some((vec, index)) = self.index_pair(range.start) {
-            Iter {
-                rope: self,
-                vec,
-                index,
-                absolute_index: range.start,
-            }
-            .take(range.end - range.start)
-        } else {
-            panic!("Wrong range: {:?}", range);
+    /// Like [`Self::quoted`], returning `None` if the quoted DNA would be
+    /// longer than `usize::MAX` bases.
+    pub fn checked_quoted(source: DnaRope, level: usize) -> Option<DnaRope> {
+        let Some(root) = &source.root else {
+            return Some(source);
+        };
+        if level == 0 {
+            return Some(source);
+        }
+        let mut leaves = vec![];
+        collect_leaves(root, &mut leaves);
+        if leaves.iter().all(|x| matches!(***x, Node::Leaf(_))) {
+            let quoted = Quoted::new(source, level)?;
+            return Some(DnaRope {
+                root: Some(Rc::new(Node::Quoted(quoted))),
+            });
+        }
+        // Quoting distributes over concatenation, so a quoted part of the
+        // source is quoted further instead of becoming part of a source.
+        let mut len: usize = 0;
+        let mut quoted = vec![];
+        for leaf in leaves {
+            let node = match &**leaf {
+                Node::Quoted(x) => Quoted::new(x.source.clone(), x.level.checked_add(level)?)?,
+                _ => Quoted::new(
+                    DnaRope {
+                        root: Some(leaf.clone()),
+                    },
+                    level,
+                )?,
+            };
+            len = len.checked_add(node.len)?;
+            quoted.push(Rc::new(Node::Quoted(node)));
         }
+        Some(DnaRope {
+            root: build(&quoted),
+        })
+    }
+
+    /// Returns the copy of the given range sharing chunks with `self`.
+    pub fn slice(&self, range: Range<usize>) -> DnaRope {
+        let Some(root) = &self.root else {
+            return DnaRope::new();
+        };
+        let (_, suffix) = split(root, range.start);
+        let root = suffix.and_then(|x| split(&x, range.end.saturating_sub(range.start)).0);
+        DnaRope { root }
+    }
+
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let Some(root) = self.root.take() else {
+            return DnaRope::new();
+        };
+        let (prefix, suffix) = split(&root, at);
+        self.root = prefix;
+        DnaRope { root: suffix }
+    }
+
+    pub fn split_by_ranges(self, ranges: &[Range<usize>]) -> Vec<DnaRope> {
+        ranges.iter().map(|x| self.slice(x.clone())).collect()
     }
 }
 
 #[derive(Clone)]
 pub struct Iter<'a> {
-    pub rope: &'a DnaRope,
-    pub vec: usize,
-    pub index: usize,
-    pub absolute_index: usize,
+    rope: &'a DnaRope,
+    /// Storage of the current chunk, which starts at `offset` in `words`
+    /// and at `chunk_start` in the rope.
+    words: &'a [u64],
+    offset: usize,
+    chunk_start: usize,
+    chunk_len: usize,
+    /// Decoder positioned at `pos` while the current leaf is a lazily
+    /// quoted node read in order, `words` are unused then.
+    quoted: Option<Box<QuoteDecoder<'a>>>,
+    pos: usize,
 }
 
 impl<'a> Iter<'a> {
+    /// Returns the position of the first occurrence of `key` at or after the
+    /// position of the iterator. The DNA is read [`WORD_BASES`] bases at a
+    /// time: up to four leading bases of the key are compared at every
+    /// offset of a word at once, and the few candidates left are compared
+    /// with the whole key a word at a time.
     pub fn dna_search(&self, key: &[Dna]) -> Option<usize> {
-        if key.is_empty() {
+        let start = self.pos;
+        let end = self.rope.len().checked_sub(key.len())?;
+        if key.is_empty() || start > end {
             return None;
         }
-
-        let key_len = key.len();
-        if key_len == 1 {
-            let key = key[0];
-            let mut iter = self.clone();
-            let pos = self.pos();
-            return iter.position(|&x| x == key).map(|x| pos + x);
-        }
-
-        let mut dfa = vec![[0; 4]; key_len];
-        dfa[0][key[0] as usize] = 1;
-        let mut x = 0;
-        for j in 1..key_len {
-            dfa[j] = dfa[x];
-            let k = key[j] as usize;
-            dfa[j][k] = j + 1;
-            x = dfa[x][k];
-        }
+        let (key_words, _) = packed::pack(key.iter().cloned());
+        let key_masks: Vec<_> = key
+            .chunks(WORD_BASES)
+            .map(|x| packed::mask(x.len()))
+            .collect();
+        let leading = &key[..key.len().min(4)];
 
         let mut iter = self.clone();
-        let mut j = 0;
-        while let Some(dna) = iter.next() {
-            j = dfa[j][*dna as usize];
-            if j == key_len {
-                return Some(iter.pos() - key_len);
+        // DNA read from `offset` on, packed like chunks.
+        let mut words: Vec<u64> = vec![];
+        let mut offset = start;
+        let mut block = start;
+        while block <= end {
+            // Every occurrence starting in the block must be in `words`.
+            let needed = block + WORD_BASES - 1 + key.len();
+            while offset + words.len() * WORD_BASES < needed.min(self.rope.len()) {
+                let n = (self.rope.len() - iter.pos).min(WORD_BASES);
+                words.push(iter.read_word(n)?);
+            }
+            let index = block - offset;
+            let mut candidates = leading.iter().enumerate().fold(!0, |x, (i, base)| {
+                x & packed::find_base(packed::word_at(&words, index + i), *base)
+            });
+            candidates &= packed::mask(end - block + 1);
+            while candidates != 0 {
+                let i = candidates.trailing_zeros() as usize / 2;
+                candidates &= candidates - 1;
+                let mut key = key_words.iter().zip(&key_masks).enumerate();
+                let found = key.all(|(j, (word, mask))| {
+                    let at = index + i + j * WORD_BASES;
+                    (packed::word_at(&words, at) ^ word) & mask == 0
+                });
+                if found {
+                    return Some(block + i);
+                }
+            }
+            block += WORD_BASES;
+            // Drops the words no later block needs.
+            let done = (block - offset) / WORD_BASES;
+            if done > 64 {
+                words.drain(..done);
+                offset += done * WORD_BASES;
             }
         }
         None
     }
 
     pub fn pos(&self) -> usize {
-        self.absolute_index
+        self.pos
     }
 
     pub fn step_back(&mut self) {
-        if self.absolute_index == 0 {
-            return;
-        }
+        self.seek(self.pos.saturating_sub(1));
+    }
 
-        if self.index != 0 {
-            self.index -= 1;
-            self.absolute_index -= 1;
-            return;
+    pub(super) fn seek(&mut self, pos: usize) {
+        let pos = pos.min(self.rope.len());
+        if pos != self.pos && self.quoted.is_some() {
+            self.quoted = None;
+            self.chunk_len = 0;
         }
+        self.pos = pos;
+    }
 
-        loop {
-            if self.index == 0 {
-                if self.vec == 0 {
-                    return;
-                }
+    pub(super) fn rope_len(&self) -> usize {
+        self.rope.len()
+    }
 
-                self.vec -= 1;
-                self.index = self.rope.dna[self.vec].len();
+    /// Advances past `prefix` if the remaining DNA starts with it, comparing
+    /// [`WORD_BASES`] bases at once. The position is unspecified otherwise.
+    pub fn skip_prefix(&mut self, prefix: &[Dna]) -> bool {
+        prefix
+            .chunks(WORD_BASES)
+            .all(|x| self.read_word(x.len()) == Some(packed::pack_word(x)))
+    }
 
-                if self.index != 0 {
-                    self.index -= 1;
-                    self.absolute_index -= 1;
-                    return;
-                }
+    /// Reads the next `n <= WORD_BASES` bases packed into a word.
+    pub(super) fn read_word(&mut self, n: usize) -> Option<u64> {
+        if self.pos + n > self.rope.len() {
+            return None;
+        }
+        let mut word = 0;
+        let mut read = 0;
+        while read < n {
+            let index = self.pos.wrapping_sub(self.chunk_start);
+            if index >= self.chunk_len {
+                self.load();
+                continue;
             }
+            let count = (self.chunk_len - index).min(n - read);
+            let bases = match &mut self.quoted {
+                Some(decoder) => decoder.read_word(count),
+                None => packed::word_at(self.words, self.offset + index) & packed::mask(count),
+            };
+            word |= bases << (2 * read);
+            read += count;
+            self.pos += count;
         }
+        Some(word)
+    }
+
+    /// Points the iterator to the chunk containing `pos`, false at the end.
+    fn load(&mut self) -> bool {
+        let Some(root) = &self.rope.root else {
+            return false;
+        };
+        if self.pos >= root.len() {
+            return false;
+        }
+        let (leaf, chunk_start) = root.locate(self.pos);
+        self.chunk_start = chunk_start;
+        self.chunk_len = leaf.len();
+        self.quoted = None;
+        let chunk = match leaf {
+            Node::Leaf(chunk) => chunk,
+            Node::Quoted(quoted) => {
+                let decoder = QuoteDecoder::at(quoted, self.pos - chunk_start);
+                self.quoted = Some(Box::new(decoder));
+                return true;
+            }
+            Node::Branch { .. } => unreachable!("located a branch"),
+        };
+        self.words = &chunk.data;
+        self.offset = chunk.start;
+        true
     }
 }
 
@@ -313,39 +890,25 @@ impl<'a> Iterator for Iter<'a> {
     type Item = &'a Dna;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.vec == self.rope.dna.len() {
+        if self.pos.wrapping_sub(self.chunk_start) >= self.chunk_len && !self.load() {
             return None;
         }
-
-        let dna = loop {
-            let dna = &self.rope.dna[self.vec];
-            if dna.len() == self.index {
-                self.vec += 1;
-                self.index = 0;
-                if self.vec == self.rope.dna.len() {
-                    return None;
-                }
-            } else {
-                break dna;
-            }
+        let result = match &mut self.quoted {
+            Some(decoder) => packed::base_ref(decoder.next().expect("quoted DNA is too short")),
+            None => packed::base(self.words, self.offset + self.pos - self.chunk_start),
         };
-
-        if self.index == dna.len() {
-            return None;
-        }
-
-        let result = dna.get(self.index);
-        self.index += 1;
-        self.absolute_index += 1;
-
-        result
+        self.pos += 1;
+        Some(result)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::matchreplace::protect;
     use super::Dna::{self, *};
-    use super::DnaRope;
+    use super::{DnaRope, MERGE_LEN, Node};
+
+    use std::rc::Rc;
 
     fn sample_dna() -> DnaRope {
         DnaRope::from_raw(vec![
@@ -365,9 +928,90 @@ mod tests {
         ])
     }
 
+    /// Checks cached lengths, heights and the AVL invariant.
+    fn check_tree(node: &Node) -> (usize, usize, usize) {
+        match node {
+            Node::Leaf(chunk) => {
+                assert!(chunk.len() > 0);
+                (chunk.len(), 0, 1)
+            }
+            Node::Quoted(quoted) => {
+                assert!(quoted.level > 0 && !quoted.source.is_empty());
+                let sources = leaves(&quoted.source);
+                assert!(sources.iter().all(|x| matches!(x, Node::Leaf(_))));
+                (quoted.len, 0, 1)
+            }
+            Node::Branch {
+                left,
+                right,
+                len,
+                height,
+                leaves,
+            } => {
+                let l = check_tree(left);
+                let r = check_tree(right);
+                assert!(l.1.abs_diff(r.1) <= 1);
+                assert_eq!(*len, l.0 + r.0);
+                assert_eq!(*height, l.1.max(r.1) + 1);
+                assert_eq!(*leaves, l.2 + r.2);
+                (*len, *height, *leaves)
+            }
+        }
+    }
+
+    fn leaves(dna_rope: &DnaRope) -> Vec<&Node> {
+        fn collect<'a>(node: &'a Node, leaves: &mut Vec<&'a Node>) {
+            match node {
+                Node::Branch { left, right, .. } => {
+                    collect(left, leaves);
+                    collect(right, leaves);
+                }
+                leaf => leaves.push(leaf),
+            }
+        }
+        let mut leaves = vec![];
+        if let Some(root) = &dna_rope.root {
+            collect(root, &mut leaves);
+        }
+        leaves
+    }
+
+    fn storage(dna_rope: &DnaRope) -> Vec<Rc<[u64]>> {
+        leaves(dna_rope)
+            .into_iter()
+            .map(|x| match x {
+                Node::Leaf(chunk) => chunk.data.clone(),
+                _ => panic!("not a chunk"),
+            })
+            .collect()
+    }
+
+    /// Concatenates leaves without the rope iterator.
+    fn chunks(dna_rope: &DnaRope) -> Vec<Dna> {
+        let mut dna = vec![];
+        for leaf in leaves(dna_rope) {
+            match leaf {
+                Node::Leaf(chunk) => dna.extend(chunk.iter()),
+                Node::Quoted(quoted) => dna.extend(protect(quoted.level, &chunks(&quoted.source))),
+                Node::Branch { .. } => unreachable!(),
+            }
+        }
+        dna
+    }
+
+    fn check_rope(dna_rope: &DnaRope, expected: &[Dna]) {
+        if let Some(root) = &dna_rope.root {
+            check_tree(root);
+        }
+        assert_eq!(dna_rope.len(), expected.len());
+        assert_eq!(chunks(dna_rope), expected);
+        assert_eq!(dna_rope.iter().cloned().collect::<Vec<_>>(), expected);
+    }
+
     #[test]
     fn next() {
         let dna_rope = sample_dna();
+        assert_eq!(dna_rope.rope_count(), 10);
 
         let mut iter = dna_rope.iter();
 
@@ -412,43 +1056,27 @@ mod tests {
         assert_eq!(iter.next(), Some(&F));
     }
 
-    fn check_index_pair(dna_rope: &DnaRope) {
-        let data = (0..dna_rope.iter().count())
-            .map(|x| match dna_rope.index_pair(x) {
-                Some((vec, index)) => &dna_rope.dna[vec][index],
-                None => panic!(),
-            })
-            .collect::<Vec<_>>();
-
-        assert_eq!(data, dna_rope.iter().collect::<Vec<_>>());
-    }
-
     #[test]
-    fn index_pair() {
-        let dna_rope = DnaRope::from(vec![]);
-        assert_eq!(dna_rope.index_pair(0), None);
-
-        let dna_rope = DnaRope::from(vec![I]);
-        assert_eq!(dna_rope.index_pair(1), None);
+    fn seek() {
+        let dna_rope = DnaRope::new();
+        let mut iter = dna_rope.iter();
+        dna_rope.iter_seek(&mut iter, 1);
+        assert_eq!((iter.pos(), iter.next()), (0, None));
 
         let dna_rope = DnaRope::from_raw(vec![vec![], vec![], vec![], vec![F]]);
-        assert_eq!(dna_rope.dna[dna_rope.index_pair(0).unwrap().0][0], F);
+        assert_eq!(dna_rope.iter().next(), Some(&F));
 
         let dna_rope = sample_dna();
-        check_index_pair(&dna_rope);
-        check_index_pair(&DnaRope::from_raw(vec![
-            vec![C, I, F, F, F],
-            vec![],
-            vec![],
-            vec![],
-            vec![],
-            vec![I],
-            vec![],
-            vec![],
-            vec![],
-            vec![P, P, I, I, C, C, F, F],
-            vec![],
-        ]));
+        let expected = dna_rope.as_vec();
+        let mut iter = dna_rope.iter();
+        for at in (0..expected.len()).rev() {
+            dna_rope.iter_seek(&mut iter, at);
+            assert_eq!(iter.clone().next(), Some(expected[at]));
+            assert_eq!(iter.collect::<Vec<_>>(), expected[at..]);
+            iter = dna_rope.iter();
+        }
+        dna_rope.iter_seek(&mut iter, expected.len() + 1);
+        assert_eq!((iter.pos(), iter.next()), (expected.len(), None));
     }
 
     fn sample_three_group(a: usize, b: usize, c: usize) -> Vec<Vec<Dna>> {
@@ -469,41 +1097,24 @@ mod tests {
 
     #[test]
     fn split_off() {
-        let len = 10 + 20 + 30;
-        for at in 0..=len {
-            let arr = sample_three_group(10, 20, 30);
-            let mut prefix: Vec<&Dna> = arr[0]
-                .iter()
-                .chain(arr[1].iter())
-                .chain(arr[2].iter())
-                .collect();
-            let suffix = prefix.split_off(at);
+        let flat = sample_three_group_flat(10, 20, 30);
+        for at in 0..=flat.len() {
             let mut dna_rope_prefix = sample_three_group_dna(10, 20, 30);
             let dna_rope_suffix = dna_rope_prefix.split_off(at);
-
-            assert_eq!(dna_rope_prefix.dna.len(), dna_rope_prefix.index.len());
-            if !dna_rope_prefix.index.is_empty() {
-                assert_eq!(dna_rope_prefix.len(), prefix.len());
-            }
-
-            let dna_rope_prefix = dna_rope_prefix.as_vec();
-            assert_eq!(dna_rope_prefix, prefix);
-
-            let dna_rope_suffix = dna_rope_suffix.as_vec();
-            assert_eq!(dna_rope_suffix, suffix);
+            check_rope(&dna_rope_prefix, &flat[..at]);
+            check_rope(&dna_rope_suffix, &flat[at..]);
         }
     }
 
     #[test]
-    fn copy_from_range() {
+    fn get_range() {
         let source = sample_three_group_flat(10, 20, 30);
         let arr: Vec<_> = source.iter().collect();
         let dna_rope = sample_three_group_dna(10, 20, 30);
         for i in 0..arr.len() {
             for j in i..=arr.len() {
-                let expected = &arr[i..j];
-                let actual = &dna_rope.iter_from_range(i..j).collect::<Vec<_>>()[..];
-                assert_eq!(actual, expected);
+                assert_eq!(dna_rope.get_range(i..j), &arr[i..j]);
+                check_rope(&dna_rope.slice(i..j), &source[i..j]);
             }
         }
     }
@@ -540,6 +1151,228 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn merge() {
+        let mut dna_rope = DnaRope::new();
+        for _ in 0..MERGE_LEN {
+            dna_rope.append_dna(vec![I]);
+        }
+        assert_eq!(dna_rope.rope_count(), 1);
+        dna_rope.prepend(DnaRope::from(vec![C]));
+        assert_eq!(dna_rope.rope_count(), 2);
+
+        let defragmented = sample_dna().defragment();
+        assert_eq!(defragmented.rope_count(), 1);
+        check_rope(&defragmented, &chunks(&sample_dna()));
+    }
+
+    #[test]
+    fn merge_small_chunks() {
+        let sizes = [3, 4, 300, 2, 2, 2, 5, 1];
+        let dna: Vec<Vec<_>> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| (0..n).map(|x| [I, C, F, P][(x + i) % 4]).collect())
+            .collect();
+        let mut dna_rope = DnaRope::from_raw(dna);
+        dna_rope.append(DnaRope::quoted(DnaRope::from(vec![I, P]), 2));
+        dna_rope.append(DnaRope::from_raw(vec![vec![F; 3], vec![C; 3]]));
+        let expected = chunks(&dna_rope);
+        let large = storage(&dna_rope.slice(7..307))[0].clone();
+
+        let (merged, copied) = dna_rope.merge_small_chunks(6);
+        check_rope(&merged, &expected);
+        assert_eq!(copied, 7 + 6 + 6 + 6);
+        assert_eq!(merged.rope_count(), 6);
+        let leaves = leaves(&merged);
+        assert!(matches!(leaves[1], Node::Leaf(chunk) if Rc::ptr_eq(&chunk.data, &large)));
+        assert!(matches!(leaves[4], Node::Quoted(_)));
+
+        let (merged, copied) = merged.merge_small_chunks(6);
+        assert_eq!((merged.rope_count(), copied), (6, 0));
+        assert_eq!(DnaRope::new().merge_small_chunks(6).1, 0);
+    }
+
+    #[test]
+    fn shared_chunks() {
+        let source = sample_three_group_flat(300, 400, 300);
+        let dna_rope = DnaRope::from(source.clone());
+        let original = storage(&dna_rope);
+        assert_eq!(original.len(), 1);
+
+        let ranges = [0..600, 300..1000, 200..700, 200..700];
+        let result = dna_rope.clone().split_by_ranges(&ranges);
+        for (dna_rope, range) in result.iter().zip(ranges) {
+            check_rope(dna_rope, &source[range]);
+            assert!(
+                storage(dna_rope)
+                    .iter()
+                    .all(|x| Rc::ptr_eq(x, &original[0]))
+            );
+        }
+
+        let mut prefix = dna_rope.clone();
+        let suffix = prefix.split_off(500);
+        check_rope(&prefix, &source[..500]);
+        assert!(storage(&suffix).iter().all(|x| Rc::ptr_eq(x, &original[0])));
+    }
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn random_operations() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let bases = [I, C, F, P];
+        let mut model: Vec<Dna> = vec![];
+        let mut dna_rope = DnaRope::new();
+        for _ in 0..2000 {
+            match rng.below(5) {
+                0 | 1 => {
+                    let dna: Vec<_> = (0..rng.below(600)).map(|_| bases[rng.below(4)]).collect();
+                    model.extend(&dna);
+                    dna_rope.append_dna(dna);
+                }
+                2 => {
+                    let len = model.len() + 1;
+                    let (a, b) = (rng.below(len), rng.below(len));
+                    let range = a.min(b)..a.max(b);
+                    let slice = dna_rope.slice(range.clone());
+                    check_rope(&slice, &model[range.clone()]);
+                    model.splice(0..0, model[range].to_vec());
+                    dna_rope.prepend(slice);
+                }
+                3 if rng.below(3) == 0 => {
+                    let len = model.len() + 1;
+                    let (a, b) = (rng.below(len), rng.below(len));
+                    let range = a.min(b)..a.max(b);
+                    let level = rng.below(4);
+                    let quoted = DnaRope::quoted(dna_rope.slice(range.clone()), level);
+                    let expected = protect(level, &model[range]);
+                    check_rope(&quoted, &expected);
+                    model.extend(expected);
+                    dna_rope.append(quoted);
+                }
+                3 => {
+                    let at = rng.below(model.len() + 1);
+                    let suffix = dna_rope.split_off(at);
+                    let tail = model.split_off(at);
+                    check_rope(&suffix, &tail);
+                    if rng.below(2) == 0 {
+                        dna_rope.append(suffix);
+                        model.extend(tail);
+                    }
+                }
+                _ => {
+                    if !model.is_empty() {
+                        let key_start = rng.below(model.len());
+                        let key_len = 1 + rng.below(80.min(model.len() - key_start));
+                        let key = &model[key_start..key_start + key_len];
+                        let from = rng.below(model.len());
+                        let mut iter = dna_rope.iter();
+                        dna_rope.iter_seek(&mut iter, from);
+                        let expected = model[from..]
+                            .windows(key_len)
+                            .position(|x| x == key)
+                            .map(|x| from + x);
+                        assert_eq!(iter.dna_search(key), expected);
+                        assert_eq!(iter.skip_prefix(key), model[from..].starts_with(key));
+                        if expected == Some(from) {
+                            assert_eq!(iter.pos(), from + key_len);
+                        }
+                    }
+                }
+            }
+            check_rope(&dna_rope, &model);
+            if model.len() > 100_000 {
+                model.truncate(1000);
+                dna_rope.split_off(1000);
+            }
+        }
+    }
+
+    #[test]
+    fn quoted() {
+        let source = DnaRope::from_raw(vec![vec![I, C, F], vec![P, P, C, I]]);
+        let flat = chunks(&source);
+        assert!(DnaRope::quoted(DnaRope::new(), 3).is_empty());
+        for level in 0..12 {
+            let expected = protect(level, &flat);
+            let dna_rope = DnaRope::quoted(source.clone(), level);
+            check_rope(&dna_rope, &expected);
+            for at in 0..=expected.len() {
+                let mut iter = dna_rope.iter();
+                dna_rope.iter_seek(&mut iter, at);
+                assert_eq!(iter.cloned().collect::<Vec<_>>(), expected[at..]);
+                let mut prefix = dna_rope.clone();
+                let suffix = prefix.split_off(at);
+                check_rope(&prefix, &expected[..at]);
+                check_rope(&suffix, &expected[at..]);
+            }
+        }
+
+        let source = DnaRope::from(flat.repeat(8));
+        let dna_rope = DnaRope::quoted(DnaRope::quoted(source, 2), 3);
+        let expected = protect(5, &flat.repeat(8));
+        let mut iter = dna_rope.iter();
+        assert!(iter.skip_prefix(&expected[..20]));
+        assert_eq!(iter.dna_search(&expected[30..40]), Some(30));
+        assert_eq!(iter.cloned().collect::<Vec<_>>(), expected[20..]);
+        let Some(Node::Quoted(quoted)) = dna_rope.root.as_deref() else {
+            panic!("not quoted");
+        };
+        assert_eq!(quoted.level, 5);
+    }
+
+    #[test]
+    fn split_quoted_lazily() {
+        let flat: Vec<_> = (0..300).map(|x| [I, C, F, P][x * 7 % 5 % 4]).collect();
+        let dna_rope = DnaRope::quoted(DnaRope::from(flat.clone()), 50);
+        let expected = protect(50, &flat);
+        assert!(expected.len() > 4 << 20);
+        for at in [1, 1000, expected.len() / 3, expected.len() - 1] {
+            let mut prefix = dna_rope.clone();
+            let suffix = prefix.split_off(at);
+            assert_eq!((prefix.len(), suffix.len()), (at, expected.len() - at));
+            for part in [&prefix, &suffix] {
+                check_tree(part.root.as_ref().unwrap());
+                // Only bases around the offset are decoded, the rest stays quoted.
+                let decoded: usize = leaves(part)
+                    .iter()
+                    .map(|x| match x {
+                        Node::Leaf(chunk) => chunk.len(),
+                        _ => 0,
+                    })
+                    .sum();
+                assert!(decoded < 2 * MERGE_LEN, "{} bases decoded", decoded);
+            }
+            let start = at.saturating_sub(100);
+            let end = expected.len().min(at + 100);
+            assert_eq!(
+                prefix.get_range(start..at),
+                expected[start..at].iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                suffix.get_range(0..end - at),
+                expected[at..end].iter().collect::<Vec<_>>()
+            );
+
+            let mut iter = dna_rope.iter();
+            dna_rope.iter_seek(&mut iter, at);
+            assert!(iter.skip_prefix(&expected[at..end]));
+            iter.step_back();
+            assert_eq!(iter.next(), Some(&expected[end - 1]));
+        }
+    }
+
     #[test]
     fn iter_dna_search() {
         let dna_rope = DnaRope::from(vec![I, C, F, P]);
@@ -551,4 +1384,16 @@ mod tests {
         let dna_rope = DnaRope::from(vec![I, C, I, C, I, C, I, C, I, C, F, I, C, I, C]);
         assert_eq!(dna_rope.iter().dna_search(&[I, C, F]), Some(8));
     }
+
+    #[test]
+    fn skip_prefix() {
+        let dna = sample_three_group_flat(40, 1, 30);
+        let dna_rope = sample_three_group_dna(40, 1, 30);
+        let mut iter = dna_rope.iter();
+        assert!(iter.skip_prefix(&dna[..45]));
+        assert_eq!(iter.next(), Some(&P));
+        assert!(!dna_rope.iter().skip_prefix(&dna[1..]));
+        assert!(!dna_rope.iter().skip_prefix(&[&dna[..], &[I]].concat()));
+        assert!(dna_rope.iter().skip_prefix(&dna));
+    }
 }
@@ -0,0 +1,143 @@
+//! Precompiled matchers for `Pattern::Search`.
+
+use super::{
+    Dna, DnaRopeIter,
+    packed::{WORD_BASES, pack_word},
+};
+
+use std::collections::HashMap;
+
+/// Length of the q-grams used by the Horspool shift table.
+const Q: usize = 4;
+/// Number of searchers kept by [`SearchCache`] before it starts over.
+const CACHE_LEN: usize = 1 << 12;
+
+/// Boyer–Moore–Horspool matcher over 4-base q-grams of packed DNA.
+///
+/// The window is checked by reading its last q-gram as a single byte, so
+/// most positions are skipped without reading the rest of the window. Keys
+/// shorter than a q-gram are searched with [`DnaRopeIter::dna_search`].
+pub struct Searcher {
+    key: Vec<Dna>,
+    /// The key packed [`WORD_BASES`] bases per word with the number of bases.
+    words: Vec<(u64, usize)>,
+    /// Shift indexed by the last q-gram of the window, zero for a candidate.
+    shifts: Vec<usize>,
+    /// Shift after a candidate window has been checked.
+    candidate_shift: usize,
+}
+
+impl Searcher {
+    pub fn new(key: &[Dna]) -> Self {
+        let len = key.len();
+        let mut shifts = vec![];
+        let mut candidate_shift = 1;
+        if len >= Q {
+            shifts = vec![len - Q + 1; 1 << (2 * Q)];
+            for i in 0..len - Q {
+                shifts[pack_word(&key[i..i + Q]) as usize] = len - Q - i;
+            }
+            let last = pack_word(&key[len - Q..]) as usize;
+            candidate_shift = shifts[last];
+            shifts[last] = 0;
+        }
+        Searcher {
+            key: key.to_vec(),
+            words: key
+                .chunks(WORD_BASES)
+                .map(|x| (pack_word(x), x.len()))
+                .collect(),
+            shifts,
+            candidate_shift,
+        }
+    }
+
+    pub fn key(&self) -> &[Dna] {
+        &self.key
+    }
+
+    /// Returns the position of the first occurrence of the key at or after
+    /// the position of `iter`, like [`DnaRopeIter::dna_search`].
+    pub fn find(&self, iter: &DnaRopeIter) -> Option<usize> {
+        let len = self.key.len();
+        if len < Q {
+            return iter.dna_search(&self.key);
+        }
+        let mut iter = iter.clone();
+        let end = iter.rope_len();
+        let mut pos = iter.pos();
+        while pos + len <= end {
+            iter.seek(pos + len - Q);
+            let shift = self.shifts[iter.read_word(Q)? as usize];
+            if shift > 0 {
+                pos += shift;
+                continue;
+            }
+            iter.seek(pos);
+            if self
+                .words
+                .iter()
+                .all(|&(word, n)| iter.read_word(n) == Some(word))
+            {
+                return Some(pos);
+            }
+            pos += self.candidate_shift;
+        }
+        None
+    }
+}
+
+/// Compiled searchers keyed by the search pattern.
+#[derive(Default)]
+pub struct SearchCache {
+    searchers: HashMap<Vec<Dna>, Searcher>,
+}
+
+impl SearchCache {
+    pub fn get(&mut self, key: &[Dna]) -> &Searcher {
+        if !self.searchers.contains_key(key) {
+            if self.searchers.len() >= CACHE_LEN {
+                self.searchers.clear();
+            }
+            self.searchers.insert(key.to_vec(), Searcher::new(key));
+        }
+        &self.searchers[key]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Dna::*, DnaRope, read_dna};
+    use super::*;
+
+    #[test]
+    fn find() {
+        let dna = read_dna("ICFPICFPPICCFPICFPPCCIFICFPPCCI");
+        let dna_rope = DnaRope::from_raw(dna.chunks(3).map(|x| x.to_vec()).collect());
+        for start in 0..dna.len() {
+            for len in 1..=dna.len() - start {
+                let key = &dna[start..start + len];
+                let searcher = Searcher::new(key);
+                for from in 0..dna.len() {
+                    let mut iter = dna_rope.iter();
+                    dna_rope.iter_seek(&mut iter, from);
+                    let expected = iter.dna_search(key);
+                    assert_eq!(searcher.find(&iter), expected);
+                    assert_eq!(iter.pos(), from);
+                }
+            }
+        }
+        let searcher = Searcher::new(&[P; 40]);
+        let dna_rope = DnaRope::from([vec![I; 100], vec![P; 41]].concat());
+        assert_eq!(searcher.find(&dna_rope.iter()), Some(100));
+    }
+
+    #[test]
+    fn cache() {
+        let mut cache = SearchCache::default();
+        assert_eq!(cache.get(&[I, C, F, P]).key(), &[I, C, F, P]);
+        cache.get(&[I, C, F, P]);
+        cache.get(&[P]);
+        assert_eq!(cache.searchers.len(), 2);
+    }
+}
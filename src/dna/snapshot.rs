@@ -0,0 +1,126 @@
+//! Snapshots of the executor state.
+//!
+//! A snapshot starts with `ENDOSNAP` and a little endian `u32` version,
+//! followed by the iteration count, the remaining DNA packed four bases per
+//! byte and the RNA emitted so far. Breakpoints and tracers are not saved.
+
+use super::{
+    DnaExecutor, DnaRope,
+    codec::{invalid_data, pack, read_dna, read_rna, read_usize, write_rna, write_usize},
+};
+
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"ENDOSNAP";
+const VERSION: u32 = 1;
+
+impl DnaExecutor {
+    pub fn save_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let w = &mut writer;
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        write_usize(w, self.loops_count)?;
+        match &self.dna {
+            Some(dna) => {
+                w.write_all(&[1])?;
+                write_usize(w, dna.len())?;
+                w.write_all(&pack(dna.iter()))?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        write_usize(w, self.rna.len())?;
+        for rna in &self.rna {
+            write_rna(w, rna)?;
+        }
+        w.flush()
+    }
+
+    pub fn load_snapshot<R: Read>(mut reader: R) -> io::Result<Self> {
+        let r = &mut reader;
+        let mut magic = [0; MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a snapshot file"));
+        }
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+        let loops_count = read_usize(r)?;
+        let mut has_dna = [0];
+        r.read_exact(&mut has_dna)?;
+        let dna = match has_dna[0] {
+            0 => None,
+            1 => Some(DnaRope::from(read_dna(r)?)),
+            _ => return Err(invalid_data("invalid DNA marker")),
+        };
+        let rna_count = read_usize(r)?;
+        let mut rna = Vec::with_capacity(rna_count.min(1 << 20));
+        for _ in 0..rna_count {
+            rna.push(read_rna(r)?);
+        }
+
+        let mut dna_executor = DnaExecutor::from_rope(DnaRope::new());
+        dna_executor.dna = dna;
+        dna_executor.rna = rna;
+        dna_executor.loops_count = loops_count;
+        Ok(dna_executor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DnaExecutor, assemble};
+    use super::*;
+
+    const SOURCE: &str = "
+        rna PIIIIIP
+        rule (![2])P -> PI(0)
+        rna PCCCCCP
+        rule ->
+        rule ->
+        dna CFPCIICIIC
+    ";
+
+    fn executor() -> DnaExecutor {
+        DnaExecutor::from_rope(DnaRope::from(assemble(SOURCE).unwrap()))
+    }
+
+    #[test]
+    fn resume() {
+        let mut expected = executor();
+        expected.execute();
+
+        let mut dna_executor = executor();
+        dna_executor.execute_loops(1);
+        let mut snapshot = vec![];
+        dna_executor.save_snapshot(&mut snapshot).unwrap();
+
+        let mut resumed = DnaExecutor::load_snapshot(&snapshot[..]).unwrap();
+        assert_eq!(resumed.loops_count(), 1);
+        assert_eq!(resumed.dna_string(), dna_executor.dna_string());
+        assert_eq!(resumed.rna(), dna_executor.rna());
+
+        resumed.execute();
+        assert_eq!(resumed.rna(), expected.rna());
+        assert_eq!(resumed.loops_count(), expected.loops_count());
+
+        let mut snapshot = vec![];
+        resumed.save_snapshot(&mut snapshot).unwrap();
+        let finished = DnaExecutor::load_snapshot(&snapshot[..]).unwrap();
+        assert!(finished.is_finished());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(DnaExecutor::load_snapshot(&b"ENDOTRACE\x01"[..]).is_err());
+        let mut snapshot = vec![];
+        executor().save_snapshot(&mut snapshot).unwrap();
+        snapshot[8] = 2;
+        assert!(DnaExecutor::load_snapshot(&snapshot[..]).is_err());
+        snapshot[8] = 1;
+        snapshot.pop();
+        assert!(DnaExecutor::load_snapshot(&snapshot[..]).is_err());
+    }
+}
@@ -1,15 +1,44 @@
 use super::{
     Dna::{self, *},
-    DnaExecutor, DnaRopeIter, nat,
+    DnaRopeIter, RnaSink,
+    matchreplace::{asnat, quote},
+    nat,
+    nat::{BigNat, Nat},
+    notation::{Cursor, NotationError},
 };
 use crate::rna::Rna;
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Template {
     Base(Dna),
     NumberLevel(usize, usize),
+    /// Reference or level past `usize::MAX`. No group has such a number, so
+    /// it expands to nothing unless only the level overflows and the group
+    /// is not empty, which can not be represented.
+    NumberLevelOverflow(Nat, Nat),
     Length(usize),
+    /// Length of a group past `usize::MAX`, which is always empty.
+    LengthOverflow(BigNat),
+}
+
+impl Template {
+    /// Creates a [`Template::NumberLevel`] or, if either number does not
+    /// fit, a [`Template::NumberLevelOverflow`].
+    pub fn number_level(n: Nat, l: Nat) -> Self {
+        match (n, l) {
+            (Nat::Small(n), Nat::Small(l)) => Template::NumberLevel(n, l),
+            (n, l) => Template::NumberLevelOverflow(n, l),
+        }
+    }
+
+    /// Creates a [`Template::Length`] or, if `n` does not fit, a [`Template::LengthOverflow`].
+    pub fn length(n: Nat) -> Self {
+        match n {
+            Nat::Small(n) => Template::Length(n),
+            Nat::Big(n) => Template::LengthOverflow(n),
+        }
+    }
 }
 
 impl fmt::Display for Template {
@@ -25,13 +54,20 @@ impl fmt::Display for Template {
                     } else {
                         format!("({},{})", n, l)
                     },
+                Template::NumberLevelOverflow(n, l) =>
+                    if l == &Nat::Small(0) {
+                        format!("({})", n)
+                    } else {
+                        format!("({},{})", n, l)
+                    },
                 Template::Length(n) => format!("|{}|", n),
+                Template::LengthOverflow(n) => format!("|{}|", n),
             }
         )
     }
 }
 
-pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec<Template>> {
+pub(super) fn execute<R: RnaSink>(rna: &mut R, iter: &mut DnaRopeIter) -> Option<Vec<Template>> {
     let mut template = vec![];
     loop {
         match iter.next() {
@@ -51,7 +87,7 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
                 Some(F) | Some(P) => {
                     if let Some(l) = nat(iter) {
                         if let Some(n) = nat(iter) {
-                            template.push(Template::NumberLevel(n, l));
+                            template.push(Template::number_level(n, l));
                         } else {
                             return None;
                         }
@@ -65,13 +101,14 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
                     }
                     Some(P) => {
                         if let Some(n) = nat(iter) {
-                            template.push(Template::Length(n));
+                            template.push(Template::length(n));
                         } else {
                             return None;
                         }
                     }
                     Some(I) => {
-                        executor.add_rna(Rna::from_dna_iter(iter));
+                        let offset = iter.pos() - 3;
+                        rna.emit(offset, Rna::from_dna_iter(iter));
                     }
                     _ => return None,
                 },
@@ -81,3 +118,124 @@ pub fn execute(executor: &mut DnaExecutor, iter: &mut DnaRopeIter) -> Option<Vec
         }
     }
 }
+
+/// Parses the textual notation produced by `Display`, e.g. `IC(0)(1,2)|3|`.
+pub fn parse(text: &str) -> Result<Vec<Template>, NotationError> {
+    let mut cursor = Cursor::new(text);
+    let mut template = vec![];
+    while let Some(symbol) = cursor.peek() {
+        if let Some(base) = cursor.base() {
+            template.push(Template::Base(base));
+            continue;
+        }
+        match symbol {
+            '(' => {
+                cursor.next();
+                let n = cursor.nat()?;
+                let l = if cursor.peek() == Some(',') {
+                    cursor.next();
+                    cursor.nat()?
+                } else {
+                    Nat::Small(0)
+                };
+                cursor.expect(')')?;
+                template.push(Template::number_level(n, l));
+            }
+            '|' => {
+                cursor.next();
+                template.push(Template::length(cursor.nat()?));
+                cursor.expect('|')?;
+            }
+            _ => return Err(cursor.unexpected()),
+        }
+    }
+    Ok(template)
+}
+
+/// Encodes `template` as DNA including the terminating `IIC`, the inverse of [`execute`].
+pub fn encode(template: &[Template]) -> Vec<Dna> {
+    let mut dna = vec![];
+    for t in template {
+        match t {
+            Template::Base(base) => dna.extend(quote([base])),
+            Template::NumberLevel(n, l) => {
+                dna.extend([I, F]);
+                dna.extend(asnat(*l));
+                dna.extend(asnat(*n));
+            }
+            Template::NumberLevelOverflow(n, l) => {
+                dna.extend([I, F]);
+                dna.extend(l.to_dna());
+                dna.extend(n.to_dna());
+            }
+            Template::Length(n) => {
+                dna.extend([I, I, P]);
+                dna.extend(asnat(*n));
+            }
+            Template::LengthOverflow(n) => {
+                dna.extend([I, I, P]);
+                dna.extend(n.to_dna());
+            }
+        }
+    }
+    dna.extend([I, I, C]);
+    dna
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DnaRope, debug};
+    use super::*;
+
+    #[test]
+    fn case_template_parse() {
+        assert_eq!(
+            parse("IC (0) (1,2) |3|"),
+            Ok(vec![
+                Template::Base(I),
+                Template::Base(C),
+                Template::NumberLevel(0, 0),
+                Template::NumberLevel(1, 2),
+                Template::Length(3)
+            ])
+        );
+        assert_eq!(
+            parse("(1,)"),
+            Err(NotationError::InvalidNumber { offset: 3 })
+        );
+        assert_eq!(parse("|2"), Err(NotationError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn case_template_encode() {
+        let text = "ICFP(0)(7,3)|12|P";
+        let template = parse(text).unwrap();
+        let dna = DnaRope::from(encode(&template));
+        let mut iter = dna.iter();
+        let decoded = execute(&mut vec![], &mut iter).unwrap();
+        assert_eq!(iter.pos(), dna.len());
+        assert_eq!(debug(&decoded), text);
+    }
+
+    #[test]
+    fn case_template_overflow() {
+        let big = Nat::from_words(vec![0, 1]);
+        let text = "(18446744073709551616)(0,18446744073709551616)|18446744073709551616|(3)";
+        let template = parse(text).unwrap();
+        assert_eq!(
+            template,
+            vec![
+                Template::NumberLevelOverflow(big.clone(), Nat::Small(0)),
+                Template::NumberLevelOverflow(Nat::Small(0), big.clone()),
+                Template::length(big),
+                Template::NumberLevel(3, 0)
+            ]
+        );
+        let dna = DnaRope::from(encode(&template));
+        let mut iter = dna.iter();
+        let decoded = execute(&mut vec![], &mut iter).unwrap();
+        assert_eq!(iter.pos(), dna.len());
+        assert_eq!(decoded, template);
+        assert_eq!(debug(&decoded), text);
+    }
+}
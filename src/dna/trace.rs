@@ -0,0 +1,358 @@
+//! Recording of executed iterations.
+//!
+//! The binary format starts with `ENDOTRACE` and a version byte, followed by
+//! one record per iteration. Numbers are stored as LEB128 varints, the
+//! pattern, the template and RNA commands as DNA packed four bases per byte.
+
+use super::{
+    Dna, DnaRope, DnaRopeIter, Pattern, Step,
+    codec::{invalid_data, read_dna, read_rna, read_usize, write_dna, write_rna, write_usize},
+    debug, pattern, template,
+};
+use crate::rna::Rna;
+
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+const MAGIC: &[u8; 9] = b"ENDOTRACE";
+const VERSION: u8 = 1;
+
+/// A single recorded iteration of [`super::DnaExecutor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    pub iteration: usize,
+    /// DNA length after the iteration.
+    pub dna_len: usize,
+    pub step: Step,
+}
+
+pub trait TraceSink {
+    fn record(&mut self, event: &TraceEvent) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct BinaryTraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BinaryTraceWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(BinaryTraceWriter { writer })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> TraceSink for BinaryTraceWriter<W> {
+    fn record(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let w = &mut self.writer;
+        let step = &event.step;
+        write_usize(w, event.iteration)?;
+        write_usize(w, event.dna_len)?;
+        write_usize(w, step.prefix_len)?;
+        // 0 stands for no match, so positions are shifted by one.
+        let position = match step.position {
+            Some(x) => x
+                .checked_add(1)
+                .ok_or_else(|| invalid_data("position does not fit the trace"))?,
+            None => 0,
+        };
+        write_usize(w, position)?;
+        write_usize(w, step.env.len())?;
+        for range in &step.env {
+            write_usize(w, range.start)?;
+            write_usize(w, range.end - range.start)?;
+        }
+        write_dna(w, &pattern::encode(&step.pattern))?;
+        write_dna(w, &template::encode(&step.template))?;
+        write_usize(w, step.rna.len())?;
+        for rna in &step.rna {
+            write_rna(w, rna)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes events as JSON Lines, one object per iteration.
+pub struct JsonTraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonTraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonTraceWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> TraceSink for JsonTraceWriter<W> {
+    fn record(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let step = &event.step;
+        let env = step
+            .env
+            .iter()
+            .map(|x| format!("[{},{}]", x.start, x.end))
+            .collect::<Vec<_>>()
+            .join(",");
+        let rna = step
+            .rna
+            .iter()
+            .map(|x| format!("\"{}\"", debug(&x.to_dna())))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            self.writer,
+            "{{\"iteration\":{},\"dna_len\":{},\"prefix_len\":{},\"pattern\":\"{}\",\"template\":\"{}\",\"position\":{},\"env\":[{}],\"rna\":[{}]}}",
+            event.iteration,
+            event.dna_len,
+            step.prefix_len,
+            debug(&step.pattern),
+            debug(&step.template),
+            step.position
+                .map_or_else(|| "null".into(), |x| x.to_string()),
+            env,
+            rna
+        )
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads events written by [`BinaryTraceWriter`].
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a trace file"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(invalid_data("unsupported trace version"));
+        }
+        Ok(TraceReader { reader })
+    }
+
+    pub fn filter_events(
+        self,
+        filter: TraceFilter,
+    ) -> impl Iterator<Item = io::Result<TraceEvent>> {
+        self.filter(move |x| x.as_ref().map_or(true, |x| filter.accepts(x)))
+    }
+
+    fn read_event(&mut self, first: u8) -> io::Result<TraceEvent> {
+        let r = &mut io::Cursor::new([first]).chain(&mut self.reader);
+        let iteration = read_usize(r)?;
+        let dna_len = read_usize(r)?;
+        let prefix_len = read_usize(r)?;
+        let position = read_usize(r)?.checked_sub(1);
+        let mut env = vec![];
+        for _ in 0..read_usize(r)? {
+            let start = read_usize(r)?;
+            let end = start
+                .checked_add(read_usize(r)?)
+                .ok_or_else(|| invalid_data("group ends past usize::MAX"))?;
+            env.push(start..end);
+        }
+        let mut rna = vec![];
+        let pattern = decode(r, &mut rna, pattern::execute)?;
+        let template = decode(r, &mut rna, template::execute)?;
+        for _ in 0..read_usize(r)? {
+            rna.push(read_rna(r)?);
+        }
+        Ok(TraceEvent {
+            iteration,
+            dna_len,
+            step: Step {
+                pattern,
+                template,
+                prefix_len,
+                position,
+                env,
+                rna,
+            },
+        })
+    }
+}
+
+fn decode<R: Read, T>(
+    reader: &mut R,
+    rna: &mut Vec<Rna>,
+    execute: fn(&mut Vec<Rna>, &mut DnaRopeIter) -> Option<T>,
+) -> io::Result<T> {
+    let dna: Vec<Dna> = read_dna(reader)?;
+    let dna = DnaRope::from(dna);
+    execute(rna, &mut dna.iter()).ok_or_else(|| invalid_data("truncated rule"))
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut first = [0];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => return None,
+                Ok(_) => return Some(self.read_event(first[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Selects events while replaying a trace, unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    pub iterations: Option<Range<usize>>,
+    pub matched: Option<bool>,
+    pub pattern: Option<Vec<Pattern>>,
+    /// Only events which emitted RNA.
+    pub with_rna: bool,
+}
+
+impl TraceFilter {
+    pub fn accepts(&self, event: &TraceEvent) -> bool {
+        self.iterations
+            .as_ref()
+            .is_none_or(|x| x.contains(&event.iteration))
+            && self.matched.is_none_or(|x| x == event.step.matched())
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|x| x == &event.step.pattern)
+            && (!self.with_rna || !event.step.rna.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::DnaExecutor;
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const DNA: &str = "IIIPIIIIIPIIPIPICPIICICIIFICCIFPPIICCFPCIICIIC";
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn expected_events() -> Vec<TraceEvent> {
+        let mut dna_executor = DnaExecutor::try_from(DNA).unwrap();
+        let mut events = vec![];
+        while let Ok(step) = dna_executor.step() {
+            events.push(TraceEvent {
+                iteration: dna_executor.loops_count(),
+                dna_len: dna_executor.dna_len(),
+                step,
+            });
+        }
+        events
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let buf = SharedBuf::default();
+        let mut dna_executor = DnaExecutor::try_from(DNA).unwrap();
+        dna_executor.trace_to(BinaryTraceWriter::new(buf.clone()).unwrap());
+        dna_executor.execute();
+        assert!(dna_executor.trace_error().is_none());
+
+        let trace = buf.0.borrow().clone();
+        let events: Vec<_> = TraceReader::new(&trace[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let expected = expected_events();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(expected[0].step.rna, vec![Rna::Move]);
+        assert_eq!(events, expected);
+
+        let filter = TraceFilter {
+            with_rna: true,
+            ..TraceFilter::default()
+        };
+        let filtered: Vec<_> = TraceReader::new(&trace[..])
+            .unwrap()
+            .filter_events(filter)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(filtered, expected[..1]);
+
+        assert!(TraceReader::new(&b"ENDOTRACE\x02"[..]).is_err());
+        let mut truncated = TraceReader::new(&trace[..trace.len() - 1]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(truncated.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn corrupted_group() {
+        let mut trace = MAGIC.to_vec();
+        trace.push(VERSION);
+        // Iteration, DNA length, prefix length, position and one group.
+        for n in [0, 0, 0, 0, 1, usize::MAX, 1] {
+            write_usize(&mut trace, n).unwrap();
+        }
+        let error = TraceReader::new(&trace[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn position_overflow() {
+        let mut event = expected_events().remove(0);
+        event.step.position = Some(usize::MAX);
+        let mut writer = BinaryTraceWriter::new(vec![]).unwrap();
+        let error = writer.record(&event).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn json_lines() {
+        let buf = SharedBuf::default();
+        let mut dna_executor = DnaExecutor::try_from(DNA).unwrap();
+        dna_executor.trace_to(JsonTraceWriter::new(buf.clone()));
+        dna_executor.execute();
+
+        let trace = String::from_utf8(buf.0.borrow().clone()).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(
+            lines[0],
+            "{\"iteration\":1,\"dna_len\":11,\"prefix_len\":36,\"pattern\":\"(![2])P\",\"template\":\"PI(0)\",\"position\":3,\"env\":[[0,2]],\"rna\":[\"PIIIIIP\"]}"
+        );
+        assert_eq!(lines.len(), 2);
+    }
+}
@@ -4,8 +4,19 @@ extern crate log;
 mod dna;
 mod rna;
 
+/// Size of the contest's canvas, the default of [`RnaRenderer`].
 pub const WIDTH: u32 = 600;
 pub const HEIGHT: u32 = 600;
 
-pub use self::dna::DnaExecutor;
-pub use self::rna::{Rna, RnaRenderer};
+pub use self::dna::{
+    AsmError, AsmErrorKind, BigNat, BinaryTraceWriter, Breakpoint, BreakpointId, CancellationToken,
+    DefragmentPolicy, DefragmentStats, Disassembler, Dna, DnaExecutor, DnaParser, DnaRope,
+    DnaRopeIter, ExecutionLimits, ExecutionStatus, Instruction, JsonTraceWriter, Limit, LoadError,
+    Nat, NotationError, Observer, ParseError, ParseMode, Pattern, Progress, Searcher, Step,
+    Stopped, Template, TraceEvent, TraceFilter, TraceReader, TraceSink, assemble, disassemble,
+    encode_pattern, encode_template, parse_dna, parse_pattern, parse_template,
+};
+pub use self::rna::{
+    ImageFormat, Rna, RnaDiagnostic, RnaParseError, RnaReadError, RnaReader, RnaRenderer,
+    RnaWriter, Score, TargetImage,
+};
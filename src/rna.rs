@@ -30,9 +30,20 @@ pub enum Rna {
     AddBitmap,
     Compose,
     Clip,
+    /// Seven bases which are not a known command.
     Unknown(Vec<Dna>),
+    /// Fewer than seven bases, read when the DNA ran out.
+    Truncated(Vec<Dna>),
 }
 
+mod image;
+mod io;
+mod score;
+
+pub use self::image::ImageFormat;
+pub use self::io::{RnaParseError, RnaReadError, RnaReader, RnaWriter};
+pub use self::score::{Score, TargetImage};
+
 use super::{HEIGHT, WIDTH};
 
 use super::dna::{
@@ -42,43 +53,105 @@ use super::dna::{
 
 use self::{Dir::*, Rna::*, RnaAlpha::*, RnaColor::*};
 
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of bases of every RNA command.
+pub(crate) const RNA_LEN: usize = 7;
+
 impl Rna {
     pub fn from_dna_iter(iter: &mut DnaRopeIter) -> Self {
-        if let (Some(a1), Some(a2), Some(a3), Some(a4), Some(a5), Some(a6), Some(a7)) = (
-            iter.next(),
-            iter.next(),
-            iter.next(),
-            iter.next(),
-            iter.next(),
-            iter.next(),
-            iter.next(),
-        ) {
-            match (a1, a2, a3, a4, a5, a6, a7) {
-                (P, I, P, I, I, I, C) => AddColor(Black),
-                (P, I, P, I, I, I, P) => AddColor(Red),
-                (P, I, P, I, I, C, C) => AddColor(Green),
-                (P, I, P, I, I, C, F) => AddColor(Yellow),
-                (P, I, P, I, I, C, P) => AddColor(Blue),
-                (P, I, P, I, I, F, C) => AddColor(Magenta),
-                (P, I, P, I, I, F, F) => AddColor(Cyan),
-                (P, I, P, I, I, P, C) => AddColor(White),
-                (P, I, P, I, I, P, F) => AddAlpha(Transparent),
-                (P, I, P, I, I, P, P) => AddAlpha(Opaque),
-                (P, I, I, P, I, C, P) => EmptyBucket,
-                (P, I, I, I, I, I, P) => Move,
-                (P, C, C, C, C, C, P) => TurnCounterClockwise,
-                (P, F, F, F, F, F, P) => TurnClockwise,
-                (P, C, C, I, F, F, P) => Mark,
-                (P, F, F, I, C, C, P) => Line,
-                (P, I, I, P, I, I, P) => TryFill,
-                (P, C, C, P, F, F, P) => AddBitmap,
-                (P, F, F, P, C, C, P) => Compose,
-                (P, F, F, I, C, C, F) => Clip,
-                _ => Unknown(vec![*a1, *a2, *a3, *a4, *a5, *a6, *a7]),
+        let mut dna = Vec::with_capacity(RNA_LEN);
+        while dna.len() < RNA_LEN {
+            match iter.next() {
+                Some(base) => dna.push(*base),
+                None => break,
             }
-        } else {
-            Unknown(vec![])
         }
+        Self::from_dna(&dna)
+    }
+
+    /// Decodes a 7-base command. Fewer bases are [`Rna::Truncated`], other
+    /// bases are [`Rna::Unknown`].
+    pub fn from_dna(dna: &[Dna]) -> Self {
+        match dna {
+            [P, I, P, I, I, I, C] => AddColor(Black),
+            [P, I, P, I, I, I, P] => AddColor(Red),
+            [P, I, P, I, I, C, C] => AddColor(Green),
+            [P, I, P, I, I, C, F] => AddColor(Yellow),
+            [P, I, P, I, I, C, P] => AddColor(Blue),
+            [P, I, P, I, I, F, C] => AddColor(Magenta),
+            [P, I, P, I, I, F, F] => AddColor(Cyan),
+            [P, I, P, I, I, P, C] => AddColor(White),
+            [P, I, P, I, I, P, F] => AddAlpha(Transparent),
+            [P, I, P, I, I, P, P] => AddAlpha(Opaque),
+            [P, I, I, P, I, C, P] => EmptyBucket,
+            [P, I, I, I, I, I, P] => Move,
+            [P, C, C, C, C, C, P] => TurnCounterClockwise,
+            [P, F, F, F, F, F, P] => TurnClockwise,
+            [P, C, C, I, F, F, P] => Mark,
+            [P, F, F, I, C, C, P] => Line,
+            [P, I, I, P, I, I, P] => TryFill,
+            [P, C, C, P, F, F, P] => AddBitmap,
+            [P, F, F, P, C, C, P] => Compose,
+            [P, F, F, I, C, C, F] => Clip,
+            _ if dna.len() < RNA_LEN => Truncated(dna.to_vec()),
+            _ => Unknown(dna.to_vec()),
+        }
+    }
+}
+
+impl Rna {
+    /// Encodes the command as the bases it is decoded from.
+    pub fn to_dna(&self) -> Vec<Dna> {
+        match self {
+            AddColor(Black) => vec![P, I, P, I, I, I, C],
+            AddColor(Red) => vec![P, I, P, I, I, I, P],
+            AddColor(Green) => vec![P, I, P, I, I, C, C],
+            AddColor(Yellow) => vec![P, I, P, I, I, C, F],
+            AddColor(Blue) => vec![P, I, P, I, I, C, P],
+            AddColor(Magenta) => vec![P, I, P, I, I, F, C],
+            AddColor(Cyan) => vec![P, I, P, I, I, F, F],
+            AddColor(White) => vec![P, I, P, I, I, P, C],
+            AddAlpha(Transparent) => vec![P, I, P, I, I, P, F],
+            AddAlpha(Opaque) => vec![P, I, P, I, I, P, P],
+            EmptyBucket => vec![P, I, I, P, I, C, P],
+            Move => vec![P, I, I, I, I, I, P],
+            TurnCounterClockwise => vec![P, C, C, C, C, C, P],
+            TurnClockwise => vec![P, F, F, F, F, F, P],
+            Mark => vec![P, C, C, I, F, F, P],
+            Line => vec![P, F, F, I, C, C, P],
+            TryFill => vec![P, I, I, P, I, I, P],
+            AddBitmap => vec![P, C, C, P, F, F, P],
+            Compose => vec![P, F, F, P, C, C, P],
+            Clip => vec![P, F, F, I, C, C, F],
+            Unknown(dna) | Truncated(dna) => dna.clone(),
+        }
+    }
+}
+
+/// Writes the command as its bases, e.g. `PIIIIIP` for [`Rna::Move`].
+impl fmt::Display for Rna {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_dna().iter().try_for_each(|x| write!(f, "{}", x))
+    }
+}
+
+/// Parses the bases of a command, the inverse of `Display`.
+impl FromStr for Rna {
+    type Err = RnaParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut dna = vec![];
+        for (offset, symbol) in s.char_indices() {
+            let base = Dna::from_char(symbol)
+                .map_err(|_| RnaParseError::UnknownSymbol { symbol, offset })?;
+            dna.push(base);
+        }
+        if dna.len() > RNA_LEN {
+            return Err(RnaParseError::InvalidLength { len: dna.len() });
+        }
+        Ok(Self::from_dna(&dna))
     }
 }
 
@@ -123,19 +196,29 @@ type Position = (u32, u32);
 
 type Pixel = (u8, u8, u8, u8);
 
+/// Command which the renderer could not draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RnaDiagnostic {
+    /// Index of the command among every command rendered.
+    pub index: usize,
+    pub command: Rna,
+}
+
 pub struct RnaRenderer {
+    width: u32,
+    height: u32,
     bitmaps: Vec<Vec<Pixel>>,
     bucket_color: Vec<BucketColor>,
     bucker_alpha: Vec<BucketAlpha>,
     dir_index: usize,
     position: Position,
     mark: Position,
+    commands_count: usize,
+    diagnostics: Vec<RnaDiagnostic>,
 }
 
-const BITMAP_SIZE: usize = WIDTH as usize * HEIGHT as usize;
-
-fn new_bitmap() -> Vec<Pixel> {
-    vec![(0, 0, 0, 0); BITMAP_SIZE]
+fn new_bitmap(width: u32, height: u32) -> Vec<Pixel> {
+    vec![(0, 0, 0, 0); width as usize * height as usize]
 }
 
 impl Default for RnaRenderer {
@@ -145,14 +228,26 @@ impl Default for RnaRenderer {
 }
 
 impl RnaRenderer {
+    /// Renderer drawing on a canvas of the contest's size, `WIDTH` by `HEIGHT`.
     pub fn new() -> RnaRenderer {
+        Self::with_size(WIDTH, HEIGHT)
+    }
+
+    /// Renderer drawing on a canvas of the given size, which must not be
+    /// empty.
+    pub fn with_size(width: u32, height: u32) -> RnaRenderer {
+        assert!(width > 0 && height > 0, "empty canvas {}x{}", width, height);
         RnaRenderer {
-            bitmaps: vec![new_bitmap()],
+            width,
+            height,
+            bitmaps: vec![new_bitmap(width, height)],
             bucket_color: vec![],
             bucker_alpha: vec![],
             dir_index: 1,
             position: (0, 0),
             mark: (0, 0),
+            commands_count: 0,
+            diagnostics: vec![],
         }
     }
 
@@ -163,6 +258,8 @@ impl RnaRenderer {
     }
 
     pub fn render_command(&mut self, command: &Rna) {
+        let index = self.commands_count;
+        self.commands_count += 1;
         match command {
             AddColor(c) => self.bucket_color.push(BucketColor::from(c)),
             AddAlpha(a) => self.bucker_alpha.push(BucketAlpha::from(a)),
@@ -170,12 +267,16 @@ impl RnaRenderer {
                 self.bucket_color.clear();
                 self.bucker_alpha.clear();
             }
-            Move => match DIRS[self.dir_index] {
-                N => self.position = (self.position.0, (self.position.1 + HEIGHT - 1) % HEIGHT),
-                E => self.position = ((self.position.0 + 1) % WIDTH, self.position.1),
-                S => self.position = (self.position.0, (self.position.1 + 1) % HEIGHT),
-                W => self.position = ((self.position.0 + WIDTH - 1) % WIDTH, self.position.1),
-            },
+            Move => {
+                let (x, y) = self.position;
+                let (w, h) = (self.width, self.height);
+                self.position = match DIRS[self.dir_index] {
+                    N => (x, (y + h - 1) % h),
+                    E => ((x + 1) % w, y),
+                    S => (x, (y + 1) % h),
+                    W => ((x + w - 1) % w, y),
+                }
+            }
             TurnCounterClockwise => self.dir_index = (self.dir_index + DIRS.len() - 1) % DIRS.len(),
             TurnClockwise => self.dir_index = (self.dir_index + 1) % DIRS.len(),
             Mark => self.mark = self.position,
@@ -189,10 +290,33 @@ impl RnaRenderer {
             AddBitmap => self.add_bitmap(),
             Compose => self.compose(),
             Clip => self.clip(),
-            Unknown(_) => (),
+            Unknown(_) | Truncated(_) => {
+                debug!("command {} not rendered: {:?}", index, command);
+                self.diagnostics.push(RnaDiagnostic {
+                    index,
+                    command: command.clone(),
+                });
+            }
         }
     }
 
+    /// Commands which were not drawn, oldest first.
+    pub fn diagnostics(&self) -> &[RnaDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<RnaDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
     pub fn position(&self) -> &Position {
         &self.position
     }
@@ -241,14 +365,18 @@ impl RnaRenderer {
         self.pixel_set(to, pixel);
     }
 
+    /// Index of `p` in a bitmap, which is stored row by row.
+    fn pixel_index(&self, p: Position) -> usize {
+        p.1 as usize * self.width as usize + p.0 as usize
+    }
+
     fn pixel_set(&mut self, p: Position, c: Pixel) {
-        let index = p.1 * HEIGHT + p.0;
-        self.bitmaps[0][index as usize] = c;
+        let index = self.pixel_index(p);
+        self.bitmaps[0][index] = c;
     }
 
     fn pixel_get(&self, p: Position) -> Pixel {
-        let index = p.1 * HEIGHT + p.0;
-        self.bitmaps[0][index as usize]
+        self.bitmaps[0][self.pixel_index(p)]
     }
 
     fn try_fill(&mut self) {
@@ -275,13 +403,13 @@ impl RnaRenderer {
             if p.0 > 0 {
                 p_vec.push((p.0 - 1, p.1));
             }
-            if p.0 < WIDTH - 1 {
+            if p.0 < self.width - 1 {
                 p_vec.push((p.0 + 1, p.1));
             }
             if p.1 > 0 {
                 p_vec.push((p.0, p.1 - 1));
             }
-            if p.1 < HEIGHT - 1 {
+            if p.1 < self.height - 1 {
                 p_vec.push((p.0, p.1 + 1));
             }
         }
@@ -289,7 +417,7 @@ impl RnaRenderer {
 
     fn add_bitmap(&mut self) {
         if self.bitmaps.len() != 10 {
-            self.bitmaps.insert(0, new_bitmap());
+            self.bitmaps.insert(0, new_bitmap(self.width, self.height));
         }
     }
 
@@ -345,7 +473,66 @@ impl RnaRenderer {
             });
             result
         } else {
-            vec![0; BITMAP_SIZE * 4]
+            vec![0; self.width as usize * self.height as usize * 4]
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::dna::DnaRope;
+    use super::*;
+
+    #[test]
+    fn from_dna_iter() {
+        let dna = DnaRope::from(vec![P, I, I, I, I, I, P, C, C, C, C, C, C, C, P, F]);
+        let mut iter = dna.iter();
+        assert_eq!(Rna::from_dna_iter(&mut iter), Move);
+        assert_eq!(Rna::from_dna_iter(&mut iter), Unknown(vec![C; 7]));
+        assert_eq!(Rna::from_dna_iter(&mut iter), Truncated(vec![P, F]));
+        assert_eq!(Rna::from_dna_iter(&mut iter), Truncated(vec![]));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut renderer = RnaRenderer::new();
+        renderer.render(&[Move, Unknown(vec![C; 7]), Mark]);
+        renderer.render_command(&Truncated(vec![P]));
+        assert_eq!(
+            renderer.diagnostics(),
+            [
+                RnaDiagnostic {
+                    index: 1,
+                    command: Unknown(vec![C; 7])
+                },
+                RnaDiagnostic {
+                    index: 3,
+                    command: Truncated(vec![P])
+                }
+            ]
+        );
+        assert_eq!(renderer.take_diagnostics().len(), 2);
+        assert!(renderer.diagnostics().is_empty());
+        assert_eq!(renderer.position(), &(1, 0));
+    }
+
+    #[test]
+    fn canvas_size() {
+        let mut renderer = RnaRenderer::with_size(5, 2);
+        assert_eq!((renderer.width(), renderer.height()), (5, 2));
+        renderer.render(&[
+            AddColor(White),
+            TurnCounterClockwise,
+            Move,
+            TurnCounterClockwise,
+            Move,
+        ]);
+        assert_eq!(renderer.position(), &(4, 1));
+        renderer.render(&[TryFill, EmptyBucket, AddColor(Red), Mark, Line]);
+        let pixels = renderer.to_vec(0);
+        assert_eq!(pixels.len(), 5 * 2 * 4);
+        assert!(pixels[..36].iter().all(|&x| x == 255));
+        assert_eq!(pixels[36..], [255, 0, 0, 255]);
+        assert_eq!(renderer.to_vec(1), vec![0; 40]);
+    }
+}
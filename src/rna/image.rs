@@ -0,0 +1,226 @@
+//! Writes the bitmaps of a [`RnaRenderer`] as PNG, PPM or PAM images.
+//!
+//! Bitmaps store colors premultiplied by alpha. The top bitmap is the picture
+//! drawn by the RNA, its alpha is ignored like in [`RnaRenderer::to_vec`], so
+//! it is written without an alpha channel. The other bitmaps are written
+//! with straight alpha, except as PPM which has no alpha channel and gets
+//! the colors as stored, that is composed over black.
+
+use super::{Pixel, RnaRenderer};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    /// Binary portable pixmap, `P6`.
+    Ppm,
+    /// Portable arbitrary map, `P7`.
+    Pam,
+}
+
+impl ImageFormat {
+    /// Returns the format for a file extension, ignoring case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pam" => Some(ImageFormat::Pam),
+            _ => None,
+        }
+    }
+
+    pub(super) fn from_path(path: &Path) -> io::Result<Self> {
+        path.extension()
+            .and_then(|x| x.to_str())
+            .and_then(Self::from_extension)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown image format: {}", path.display()),
+                )
+            })
+    }
+}
+
+impl RnaRenderer {
+    /// Number of bitmaps in the stack, the top one has index 0.
+    pub fn bitmaps_count(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    /// Writes the bitmap at `index` as an image, failing with
+    /// [`io::ErrorKind::InvalidInput`] if there is no such bitmap.
+    pub fn write_image<W: Write>(
+        &self,
+        index: usize,
+        format: ImageFormat,
+        writer: W,
+    ) -> io::Result<()> {
+        let bitmap = self.bitmaps.get(index).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no bitmap {} in a stack of {}", index, self.bitmaps.len()),
+            )
+        })?;
+        let alpha = index != 0 && format != ImageFormat::Ppm;
+        let data = to_bytes(bitmap, alpha);
+        write_bytes(self.width, self.height, &data, alpha, format, writer)
+    }
+
+    /// Writes the bitmap at `index` to `path`, in the format given by its
+    /// extension, see [`ImageFormat::from_extension`].
+    pub fn save_image<P: AsRef<Path>>(&self, index: usize, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)?;
+        self.write_image(index, format, BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Writes an image of RGB bytes, or of RGBA bytes with straight alpha for
+/// the formats having an alpha channel.
+pub(super) fn write_bytes<W: Write>(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    alpha: bool,
+    format: ImageFormat,
+    mut writer: W,
+) -> io::Result<()> {
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(&mut writer, width, height);
+            encoder.set_color(if alpha {
+                png::ColorType::Rgba
+            } else {
+                png::ColorType::Rgb
+            });
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(data)?;
+            writer.finish()?;
+        }
+        ImageFormat::Ppm => {
+            write!(writer, "P6\n{} {}\n255\n", width, height)?;
+            debug_assert!(!alpha);
+            writer.write_all(data)?;
+        }
+        ImageFormat::Pam => {
+            write!(
+                writer,
+                "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL 255\nTUPLTYPE {}\nENDHDR\n",
+                width,
+                height,
+                if alpha { 4 } else { 3 },
+                if alpha { "RGB_ALPHA" } else { "RGB" }
+            )?;
+            writer.write_all(data)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Returns the pixels as RGB bytes, premultiplied, or as RGBA bytes with
+/// straight alpha.
+pub(super) fn to_bytes(bitmap: &[Pixel], alpha: bool) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bitmap.len() * if alpha { 4 } else { 3 });
+    for &(r, g, b, a) in bitmap {
+        if alpha {
+            result.extend([
+                unpremultiply(r, a),
+                unpremultiply(g, a),
+                unpremultiply(b, a),
+                a,
+            ]);
+        } else {
+            result.extend([r, g, b]);
+        }
+    }
+    result
+}
+
+fn unpremultiply(c: u8, a: u8) -> u8 {
+    if a == 0 {
+        0
+    } else {
+        ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIXELS: usize = 4 * 3;
+
+    fn sample() -> RnaRenderer {
+        let mut renderer = RnaRenderer::with_size(4, 3);
+        renderer.bitmaps[0][0] = (255, 0, 0, 255);
+        renderer.bitmaps[0][1] = (0, 127, 0, 127);
+        renderer.add_bitmap();
+        renderer.bitmaps[0][4] = (0, 0, 255, 255);
+        renderer
+    }
+
+    fn decode_png(data: &[u8]) -> (png::ColorType, Vec<u8>) {
+        let mut reader = png::Decoder::new(data).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (4, 3));
+        buf.truncate(info.buffer_size());
+        (info.color_type, buf)
+    }
+
+    fn write(renderer: &RnaRenderer, index: usize, format: ImageFormat) -> Vec<u8> {
+        let mut data = vec![];
+        renderer.write_image(index, format, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn png() {
+        let renderer = sample();
+        let (color_type, top) = decode_png(&write(&renderer, 0, ImageFormat::Png));
+        assert_eq!(color_type, png::ColorType::Rgb);
+        assert_eq!(top.len(), PIXELS * 3);
+        assert_eq!(top[..3], [0, 0, 0]);
+        assert_eq!(top[12..15], [0, 0, 255]);
+
+        let (color_type, below) = decode_png(&write(&renderer, 1, ImageFormat::Png));
+        assert_eq!(color_type, png::ColorType::Rgba);
+        assert_eq!(below[..8], [255, 0, 0, 255, 0, 255, 0, 127]);
+    }
+
+    #[test]
+    fn ppm_pam() {
+        let renderer = sample();
+        let ppm = write(&renderer, 1, ImageFormat::Ppm);
+        let header = b"P6\n4 3\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm[header.len()..header.len() + 6], [255, 0, 0, 0, 127, 0]);
+        assert_eq!(ppm.len(), header.len() + PIXELS * 3);
+
+        let pam = write(&renderer, 1, ImageFormat::Pam);
+        let header = String::from_utf8_lossy(&pam[..pam.len() - PIXELS * 4]);
+        assert_eq!(
+            header,
+            "P7\nWIDTH 4\nHEIGHT 3\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n"
+        );
+        let pam = write(&renderer, 0, ImageFormat::Pam);
+        assert_eq!(pam.len() - PIXELS * 3, header.len() - 6);
+    }
+
+    #[test]
+    fn missing_bitmap() {
+        let renderer = sample();
+        assert_eq!(renderer.bitmaps_count(), 2);
+        let error = renderer
+            .write_image(2, ImageFormat::Png, io::sink())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(ImageFormat::from_extension("PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_extension("bmp"), None);
+    }
+}
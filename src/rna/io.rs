@@ -0,0 +1,305 @@
+//! The `.rna` text format: commands as 7-letter strings of bases.
+//!
+//! The contest tools write the commands back to back followed by a single
+//! `0xFF` byte, other tools put every command on its own line. The reader
+//! accepts both, the writer produces the former without the `0xFF` byte.
+
+use super::{RNA_LEN, Rna};
+use crate::dna::Dna::{self, *};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Byte terminating RNA written by the contest's `decode.c`.
+const END_OF_RNA: u8 = 0xFF;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RnaParseError {
+    UnknownSymbol {
+        symbol: char,
+        offset: usize,
+    },
+    /// A command of `len` bases, more than seven. Only parsing a single
+    /// command reports it, [`RnaReader`] splits longer input into commands.
+    InvalidLength {
+        len: usize,
+    },
+}
+
+impl fmt::Display for RnaParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RnaParseError::UnknownSymbol { symbol, offset } => {
+                write!(f, "unknown symbol {:?} at offset {}", symbol, offset)
+            }
+            RnaParseError::InvalidLength { len } => {
+                write!(f, "command has {} bases, more than {}", len, RNA_LEN)
+            }
+        }
+    }
+}
+
+impl Error for RnaParseError {}
+
+#[derive(Debug)]
+pub enum RnaReadError {
+    Io(io::Error),
+    Parse(RnaParseError),
+}
+
+impl fmt::Display for RnaReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RnaReadError::Io(e) => write!(f, "i/o error: {}", e),
+            RnaReadError::Parse(e) => write!(f, "parse error: {}", e),
+        }
+    }
+}
+
+impl Error for RnaReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RnaReadError::Io(e) => Some(e),
+            RnaReadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for RnaReadError {
+    fn from(value: io::Error) -> Self {
+        RnaReadError::Io(value)
+    }
+}
+
+impl From<RnaParseError> for RnaReadError {
+    fn from(value: RnaParseError) -> Self {
+        RnaReadError::Parse(value)
+    }
+}
+
+/// Streams commands out of `.rna` text, skipping whitespace between bases.
+/// Reading stops at the end of the input or at a `0xFF` byte, a partial
+/// command before it is [`Rna::Truncated`].
+pub struct RnaReader<R: Read> {
+    reader: BufReader<R>,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: Read> RnaReader<R> {
+    pub fn new(reader: R) -> Self {
+        RnaReader {
+            reader: BufReader::new(reader),
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the next base, `None` at the end of the RNA.
+    fn next_base(&mut self) -> Result<Option<Dna>, RnaReadError> {
+        loop {
+            let Some(&byte) = self.reader.fill_buf()?.first() else {
+                return Ok(None);
+            };
+            if byte == END_OF_RNA {
+                return Ok(None);
+            }
+            self.reader.consume(1);
+            self.offset += 1;
+            match byte {
+                b'I' => return Ok(Some(I)),
+                b'C' => return Ok(Some(C)),
+                b'F' => return Ok(Some(F)),
+                b'P' => return Ok(Some(P)),
+                _ if byte.is_ascii_whitespace() => (),
+                _ => {
+                    return Err(RnaParseError::UnknownSymbol {
+                        symbol: byte as char,
+                        offset: self.offset - 1,
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+
+    fn read_command(&mut self) -> Result<Option<Rna>, RnaReadError> {
+        let mut dna = Vec::with_capacity(RNA_LEN);
+        while dna.len() < RNA_LEN {
+            match self.next_base()? {
+                Some(base) => dna.push(base),
+                None if dna.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        Ok(Some(Rna::from_dna(&dna)))
+    }
+}
+
+impl<R: Read> Iterator for RnaReader<R> {
+    type Item = Result<Rna, RnaReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_command().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Writes commands in the `.rna` text format.
+///
+/// The reader skips whitespace, so a command shorter than 7 bases would
+/// merge with the next one: it can only be the last command written.
+pub struct RnaWriter<W: Write> {
+    writer: W,
+    newlines: bool,
+    /// Whether a command shorter than 7 bases was written.
+    short: bool,
+}
+
+impl<W: Write> RnaWriter<W> {
+    /// Writes commands back to back like the contest tools.
+    pub fn new(writer: W) -> Self {
+        RnaWriter {
+            writer,
+            newlines: false,
+            short: false,
+        }
+    }
+
+    /// Writes every command on its own line.
+    pub fn with_newlines(writer: W) -> Self {
+        RnaWriter {
+            writer,
+            newlines: true,
+            short: false,
+        }
+    }
+
+    /// Writes the bases of `rna`, see [`Rna::to_dna`]. Fails with
+    /// [`io::ErrorKind::InvalidInput`] after a command shorter than 7 bases.
+    pub fn write(&mut self, rna: &Rna) -> io::Result<()> {
+        if self.short {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a command shorter than 7 bases must be the last one",
+            ));
+        }
+        let mut text = rna.to_string();
+        self.short = text.len() != RNA_LEN;
+        if self.newlines {
+            text.push('\n');
+        }
+        self.writer.write_all(text.as_bytes())
+    }
+
+    pub fn write_all(&mut self, rna: &[Rna]) -> io::Result<()> {
+        rna.iter().try_for_each(|x| self.write(x))
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RnaAlpha, RnaColor};
+    use super::*;
+
+    fn sample() -> Vec<Rna> {
+        vec![
+            Rna::AddColor(RnaColor::Magenta),
+            Rna::AddAlpha(RnaAlpha::Opaque),
+            Rna::Move,
+            Rna::Clip,
+            Rna::Unknown(vec![P, P, P, P, P, P, P]),
+            Rna::Truncated(vec![C, I]),
+        ]
+    }
+
+    fn read(input: &[u8]) -> Result<Vec<Rna>, RnaReadError> {
+        RnaReader::new(input).collect()
+    }
+
+    #[test]
+    fn display_from_str() {
+        for rna in sample() {
+            assert_eq!(rna.to_string().parse::<Rna>(), Ok(rna));
+        }
+        assert_eq!(Rna::Move.to_string(), "PIIIIIP");
+        assert_eq!(
+            "PIIXIIP".parse::<Rna>(),
+            Err(RnaParseError::UnknownSymbol {
+                symbol: 'X',
+                offset: 3
+            })
+        );
+        assert_eq!(
+            "PIIIIIPI".parse::<Rna>(),
+            Err(RnaParseError::InvalidLength { len: 8 })
+        );
+        assert_eq!(
+            RnaParseError::InvalidLength { len: 8 }.to_string(),
+            "command has 8 bases, more than 7"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer = RnaWriter::new(vec![]);
+        writer.write_all(&sample()).unwrap();
+        let packed = writer.finish().unwrap();
+        assert_eq!(packed.len(), 7 * 5 + 2);
+        assert_eq!(read(&packed).unwrap(), sample());
+
+        let mut writer = RnaWriter::with_newlines(vec![]);
+        writer.write_all(&sample()).unwrap();
+        let lines = writer.finish().unwrap();
+        assert_eq!(&lines[..8], b"PIPIIFC\n");
+        assert_eq!(read(&lines).unwrap(), sample());
+    }
+
+    #[test]
+    fn truncated_mid_stream() {
+        let mut rna = sample();
+        rna.swap(4, 5);
+        for mut writer in [RnaWriter::new(vec![]), RnaWriter::with_newlines(vec![])] {
+            let error = writer.write_all(&rna).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            let written = writer.finish().unwrap();
+            assert_eq!(read(&written).unwrap(), rna[..5]);
+        }
+    }
+
+    #[test]
+    fn contest_output() {
+        let mut input = b"PIPIIFCPIP\r\nIIPP PIIIIIP".to_vec();
+        input.push(END_OF_RNA);
+        assert_eq!(read(&input).unwrap(), sample()[..3]);
+        assert_eq!(read(b"").unwrap(), vec![]);
+
+        let mut reader = RnaReader::new(&b"PIIIIIPPIIX"[..]);
+        assert_eq!(reader.next().unwrap().unwrap(), Rna::Move);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(RnaReadError::Parse(RnaParseError::UnknownSymbol {
+                symbol: 'X',
+                offset: 10
+            })))
+        ));
+        assert!(reader.next().is_none());
+        assert_eq!(
+            read(b"PIIIIIP\nPII\n").unwrap(),
+            [Rna::Move, Rna::Truncated(vec![P, I, I])]
+        );
+    }
+}
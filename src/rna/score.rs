@@ -0,0 +1,211 @@
+//! Compares the picture drawn by the RNA with the contest's target picture.
+//!
+//! A pixel is incorrect when its RGB value differs from the target, the
+//! alpha of the top bitmap being ignored. The contest ranks a prefix by its
+//! risk, ten times the number of incorrect pixels plus the prefix length.
+
+use super::RnaRenderer;
+use super::image::{ImageFormat, to_bytes, write_bytes};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Picture to score against, e.g. `data/target.png`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetImage {
+    width: u32,
+    height: u32,
+    /// RGB bytes, row by row from the top left corner.
+    rgb: Vec<u8>,
+}
+
+/// Result of [`TargetImage::score`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Score {
+    pub incorrect_pixels: usize,
+    width: u32,
+    height: u32,
+    mask: Vec<bool>,
+}
+
+impl TargetImage {
+    /// Builds a target from `width * height * 3` RGB bytes.
+    pub fn from_rgb(width: u32, height: u32, rgb: Vec<u8>) -> io::Result<Self> {
+        if rgb.len() != width as usize * height as usize * 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected {}x{} RGB pixels", width, height),
+            ));
+        }
+        Ok(TargetImage { width, height, rgb })
+    }
+
+    /// Decodes a PNG image of any color type, ignoring its alpha channel.
+    pub fn from_png<R: Read>(reader: R) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        buf.truncate(info.buffer_size());
+        let samples = info.color_type.samples();
+        let rgb = buf
+            .chunks(samples)
+            .flat_map(|x| match samples {
+                1 | 2 => [x[0]; 3],
+                _ => [x[0], x[1], x[2]],
+            })
+            .collect();
+        Self::from_rgb(info.width, info.height, rgb)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_png(BufReader::new(File::open(path)?))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Compares the top bitmap of `renderer` with the target, failing with
+    /// [`io::ErrorKind::InvalidInput`] if their sizes differ.
+    pub fn score(&self, renderer: &RnaRenderer) -> io::Result<Score> {
+        if (renderer.width, renderer.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "canvas is {}x{} but the target is {}x{}",
+                    renderer.width, renderer.height, self.width, self.height
+                ),
+            ));
+        }
+        let rgb = to_bytes(&renderer.bitmaps[0], false);
+        let mask: Vec<_> = rgb
+            .chunks(3)
+            .zip(self.rgb.chunks(3))
+            .map(|(x, y)| x != y)
+            .collect();
+        Ok(Score {
+            incorrect_pixels: mask.iter().filter(|&&x| x).count(),
+            width: self.width,
+            height: self.height,
+            mask,
+        })
+    }
+}
+
+impl Score {
+    /// The contest's risk of a prefix of `prefix_len` bases drawing the
+    /// scored picture.
+    pub fn risk(&self, prefix_len: usize) -> usize {
+        10 * self.incorrect_pixels + prefix_len
+    }
+
+    /// Share of the pixels which are correct, from 0 to 1.
+    pub fn similarity(&self) -> f64 {
+        1.0 - self.incorrect_pixels as f64 / self.mask.len() as f64
+    }
+
+    /// Incorrect pixels, row by row from the top left corner.
+    pub fn mask(&self) -> &[bool] {
+        &self.mask
+    }
+
+    /// Writes the mask as an image, white where the pixels are incorrect
+    /// and black elsewhere.
+    pub fn write_mask<W: Write>(&self, format: ImageFormat, writer: W) -> io::Result<()> {
+        let rgb: Vec<_> = self
+            .mask
+            .iter()
+            .flat_map(|&x| [if x { 255 } else { 0 }; 3])
+            .collect();
+        write_bytes(self.width, self.height, &rgb, false, format, writer)
+    }
+
+    /// Writes the mask to `path`, in the format given by its extension.
+    pub fn save_mask<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)?;
+        self.write_mask(format, BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RnaAlpha, RnaColor};
+    use super::*;
+    use crate::{HEIGHT, Rna, WIDTH};
+
+    #[test]
+    fn score() {
+        let mut rgb = vec![0; 4 * 3 * 3];
+        rgb[..3].copy_from_slice(&[255, 0, 0]);
+        rgb[6..9].copy_from_slice(&[0, 0, 255]);
+        let target = TargetImage::from_rgb(4, 3, rgb).unwrap();
+
+        let mut renderer = RnaRenderer::with_size(4, 3);
+        let score = target.score(&renderer).unwrap();
+        assert_eq!(score.incorrect_pixels, 2);
+        assert_eq!(score.risk(28), 48);
+        assert_eq!(&score.mask()[..4], [true, false, true, false]);
+
+        renderer.render(&[
+            Rna::AddColor(RnaColor::Red),
+            Rna::AddAlpha(RnaAlpha::Opaque),
+            Rna::Line,
+        ]);
+        let score = target.score(&renderer).unwrap();
+        assert_eq!(score.incorrect_pixels, 1);
+        assert_eq!(score.similarity(), 1.0 - 1.0 / 12.0);
+
+        let error = target.score(&RnaRenderer::with_size(3, 4)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn png() {
+        let mut renderer = RnaRenderer::with_size(4, 3);
+        renderer.render(&[Rna::AddColor(RnaColor::Cyan), Rna::Line]);
+        let mut data = vec![];
+        renderer
+            .write_image(0, ImageFormat::Png, &mut data)
+            .unwrap();
+        let target = TargetImage::from_png(&data[..]).unwrap();
+        assert_eq!((target.width(), target.height()), (4, 3));
+        assert_eq!(target.score(&renderer).unwrap().incorrect_pixels, 0);
+
+        let score = target.score(&RnaRenderer::with_size(4, 3)).unwrap();
+        assert_eq!(score.incorrect_pixels, 1);
+        let mut mask = vec![];
+        score.write_mask(ImageFormat::Ppm, &mut mask).unwrap();
+        let header = b"P6\n4 3\n255\n";
+        assert_eq!(mask[header.len()..header.len() + 4], [255, 255, 255, 0]);
+        assert_eq!(mask.len(), header.len() + 4 * 3 * 3);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            TargetImage::from_rgb(4, 3, vec![0; 3]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(TargetImage::from_png(&b"P6"[..]).is_err());
+    }
+
+    #[test]
+    fn contest_images() {
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/data");
+        let source = TargetImage::load(format!("{}/source.png", data)).unwrap();
+        let target = TargetImage::load(format!("{}/target.png", data)).unwrap();
+        assert_eq!((target.width(), target.height()), (WIDTH, HEIGHT));
+        let blank = target.score(&RnaRenderer::new()).unwrap();
+        let pixels = WIDTH as usize * HEIGHT as usize;
+        assert!(blank.incorrect_pixels > 0 && blank.incorrect_pixels < pixels);
+        assert_ne!(source, target);
+    }
+}
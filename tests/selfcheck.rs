@@ -1,13 +1,23 @@
 extern crate endo_rs;
 
-const ENDO_DNA: &str = include_str!("../data/endo.dna");
+use endo_rs::{DnaExecutor, ParseMode, RnaRenderer, TargetImage};
 
-use endo_rs::DnaExecutor;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+const ENDO_DNA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/data/endo.dna");
 
 #[test]
 fn selfcheck_compare_to_sampled() {
-    let dna = "IIPIFFCPICICIICPIICIPPPICIIC".to_string() + ENDO_DNA;
-    let mut dna_executor = DnaExecutor::from(dna.as_str());
+    if !Path::new(ENDO_DNA).exists() {
+        eprintln!("skipping selfcheck: {} not found", ENDO_DNA);
+        return;
+    }
+    let dna = "IIPIFFCPICICIICPIICIPPPICIIC"
+        .as_bytes()
+        .chain(File::open(ENDO_DNA).unwrap());
+    let mut dna_executor = DnaExecutor::from_reader(dna, ParseMode::Lenient).unwrap();
     let rna = dna_executor.execute();
     let result = rna
         .iter()
@@ -18,3 +28,18 @@ fn selfcheck_compare_to_sampled() {
     assert_eq!(result.len(), expected.len());
     assert_eq!(result, expected);
 }
+
+#[test]
+fn empty_prefix_draws_source() {
+    if !Path::new(ENDO_DNA).exists() {
+        eprintln!("skipping empty prefix: {} not found", ENDO_DNA);
+        return;
+    }
+    let mut dna_executor =
+        DnaExecutor::from_reader(File::open(ENDO_DNA).unwrap(), ParseMode::Lenient).unwrap();
+    let mut renderer = RnaRenderer::new();
+    renderer.render(dna_executor.execute());
+    let source =
+        TargetImage::load(concat!(env!("CARGO_MANIFEST_DIR"), "/data/source.png")).unwrap();
+    assert_eq!(source.score(&renderer).unwrap().incorrect_pixels, 0);
+}